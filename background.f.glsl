@@ -0,0 +1,11 @@
+#version 110
+
+uniform vec4 top;
+uniform vec4 bottom;
+
+varying float height;
+
+void main()
+{
+    gl_FragColor = mix(bottom, top, height);
+}
@@ -0,0 +1,11 @@
+#version 110
+
+attribute vec2 position;
+
+varying float height;
+
+void main()
+{
+    gl_Position = vec4(position, 0.0, 1.0);
+    height = position.y * 0.5 + 0.5;
+}
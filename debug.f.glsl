@@ -0,0 +1,8 @@
+#version 110
+
+varying vec4 frag_color;
+
+void main()
+{
+    gl_FragColor = frag_color;
+}
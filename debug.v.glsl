@@ -0,0 +1,15 @@
+#version 110
+
+uniform mat4 transform;
+
+attribute vec3 position;
+attribute vec4 color;
+
+varying vec4 frag_color;
+
+void main()
+{
+    gl_Position = transform * vec4(position, 1.0);
+    gl_PointSize = 4.0;
+    frag_color = color;
+}
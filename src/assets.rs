@@ -0,0 +1,66 @@
+use notify::{self, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher as NotifyWatcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+// editors tend to write a file in several steps; wait for them to settle
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// a single filesystem watcher that any subsystem can register asset paths
+// with. changes are collected on notify's thread and handed to the main
+// loop through changed(), so reloads happen on the thread owning the GL
+// context.
+//
+// directories are watched rather than the files themselves, since editors
+// that save by replacing the file would otherwise drop the watch.
+pub struct Watcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    dirs: HashSet<PathBuf>,
+    files: HashSet<PathBuf>,
+}
+
+impl Watcher {
+    pub fn new() -> notify::Result<Watcher> {
+        let (tx, rx) = channel();
+        Ok(Watcher {
+            watcher: RecommendedWatcher::new(tx, DEBOUNCE)?,
+            events: rx,
+            dirs: HashSet::new(),
+            files: HashSet::new(),
+        })
+    }
+
+    pub fn watch<P: AsRef<Path>>(&mut self, path: P) -> notify::Result<()> {
+        let path = absolute(path.as_ref());
+        if let Some(dir) = path.parent() {
+            if !self.dirs.contains(dir) {
+                self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+                self.dirs.insert(dir.to_path_buf());
+            }
+        }
+        self.files.insert(path);
+        Ok(())
+    }
+
+    // registered paths that changed since the last call, each listed once
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        while let Ok(event) = self.events.try_recv() {
+            let path = match event {
+                DebouncedEvent::Write(p) | DebouncedEvent::Create(p) | DebouncedEvent::Chmod(p) => p,
+                DebouncedEvent::Rename(_, p) => p,
+                _ => continue,
+            };
+            if self.files.contains(&path) && !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+        changed
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,151 @@
+use gleam::gl;
+use gleam::gl::types::{GLfloat, GLsizei};
+
+use {GlPtr, load_program, quad_mesh};
+use buffer::{Buffer, Usage};
+use caps::Capabilities;
+use command::CommandBuffer;
+use draw::{DrawItem, Geometry};
+use label::{self, Object};
+use material::Material;
+use pass::RenderPass;
+use pipeline::PipelineState;
+use program::Program;
+use state::StateCache;
+use vertex::{VertexLayout, Packing, Format};
+use vfs::Vfs;
+
+// what's behind everything else
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Background {
+    Solid([GLfloat; 4]),
+    // from the top of the window to the bottom
+    Gradient([GLfloat; 4], [GLfloat; 4]),
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::Solid([0.0, 0.0, 0.0, 1.0])
+    }
+}
+
+// "#rrggbb"
+fn parse_color(s: &str) -> Option<[GLfloat; 4]> {
+    // checked up front: from_str_radix would take a sign, and slicing a
+    // multi-byte character would panic
+    let hex = s.strip_prefix('#').filter(|h| h.len() == 6 && h.bytes().all(|c| c.is_ascii_hexdigit()))?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok().map(|c| c as GLfloat / 255.0);
+    Some([channel(0)?, channel(2)?, channel(4)?, 1.0])
+}
+
+impl Background {
+    // "#rrggbb" for a solid color, "#rrggbb,#rrggbb" for a gradient from
+    // top to bottom
+    pub fn parse(s: &str) -> Option<Background> {
+        match s.split_once(',') {
+            Some((top, bottom)) => Some(Background::Gradient(parse_color(top)?, parse_color(bottom)?)),
+            None                => Some(Background::Solid(parse_color(s)?)),
+        }
+    }
+}
+
+pub static SHADER_FILES: [&str; 2] = [ "background.v.glsl", "background.f.glsl" ];
+
+// draws a Background. a solid one is just the pass's clear color; a
+// gradient is a full-screen quad drawn over that.
+pub struct Backdrop {
+    background: Background,
+    quad: Geometry,
+    material: Material,
+    gl: GlPtr,
+}
+
+impl Drop for Backdrop {
+    fn drop(&mut self) {
+        self.gl.delete_program(self.material.program.id);
+    }
+}
+
+impl Backdrop {
+    pub fn new(gl: &GlPtr, vfs: &dyn Vfs, caps: &Capabilities, background: Background) -> Backdrop {
+        let program = load_program(gl, vfs, caps, SHADER_FILES[0], SHADER_FILES[1]);
+        label::object(Object::Program, program, "background");
+
+        // the corners are all +-1, which halves hold exactly
+        let layout = VertexLayout::new(Packing::Interleaved, caps)
+            .attribute_as(gl.get_attrib_location(program, "position"), 2, Format::HalfFloat);
+
+        let mesh = quad_mesh();
+        let mut material = Material::new(Program::reflect(gl, program));
+        if let Background::Gradient(top, bottom) = background {
+            material.set("top", &top);
+            material.set("bottom", &bottom);
+        }
+
+        Backdrop {
+            background,
+            quad: Geometry {
+                vertex_buffers: layout.pack(&mesh.vertices).iter()
+                    .map(|data| Buffer::new(gl, gl::ARRAY_BUFFER, Usage::Static, data).labeled("background vertices"))
+                    .collect(),
+                index_buffer: Buffer::new(gl, gl::ELEMENT_ARRAY_BUFFER, Usage::Static, &mesh.indices).labeled("background indices"),
+                layout,
+                mode: gl::TRIANGLES,
+                count: mesh.indices.len() as GLsizei,
+                index_type: gl::UNSIGNED_SHORT,
+            },
+            material,
+            gl: gl.clone(),
+        }
+    }
+
+    // a pass that starts with the background in place
+    pub fn pass(&self, size: (u32, u32)) -> RenderPass {
+        match self.background {
+            Background::Solid(color)        => RenderPass::new(size).clear_color(color),
+            Background::Gradient(_, bottom) => RenderPass::new(size).clear_color(bottom),
+        }
+    }
+
+    // what pass() doesn't do with the clear alone
+    pub fn record<'a>(&'a self, cmds: &mut CommandBuffer<'a>) {
+        if let Background::Gradient(..) = self.background {
+            DrawItem {
+                geometry: &self.quad,
+                material: &self.material,
+                pipeline: PipelineState::default().depth_write(false),
+            }.record(cmds);
+        }
+    }
+
+    pub fn draw(&self, state: &mut StateCache) {
+        let mut cmds = CommandBuffer::new();
+        self.record(&mut cmds);
+        cmds.execute(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solid() {
+        assert_eq!(Background::parse("#ff0000"), Some(Background::Solid([1.0, 0.0, 0.0, 1.0])));
+        assert_eq!(Background::parse("#00FF33"), Some(Background::Solid([0.0, 1.0, 0.2, 1.0])));
+    }
+
+    #[test]
+    fn gradient() {
+        assert_eq!(Background::parse("#000000,#ffffff"),
+                   Some(Background::Gradient([0.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0])));
+    }
+
+    #[test]
+    fn invalid() {
+        for s in ["", "ff0000", "#ff000", "#ff00000", "#gg0000", "#+f+f+f", "#aéaaa",
+                  "#ff0000,", "ff0000,#ff0000", "#ff0000,#00ff00,#0000ff"] {
+            assert_eq!(Background::parse(s), None, "{:?}", s);
+        }
+    }
+}
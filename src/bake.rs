@@ -0,0 +1,61 @@
+use std::env;
+use std::process;
+
+use loader;
+use vfs::{self, Pak, Vfs};
+
+fn usage() -> ! {
+    println!("usage: hello-gl bake OUT.pak [--asset-dir DIR]");
+    process::exit(1);
+}
+
+// `hello-gl bake`: packs the given assets into a pak for --assets, looking
+// them up the same way the demo does. images go in already decoded, so
+// loading them is a copy rather than a BMP decode. assets that aren't
+// there, like an absent bindings.toml, are left out.
+pub fn main(files: &[&str]) {
+    let mut out = None;
+    let mut asset_dir = None;
+    let mut args = env::args().skip(2);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--asset-dir" => match args.next() {
+                Some(d) => asset_dir = Some(d),
+                None    => usage(),
+            },
+            _ if out.is_none() && !arg.starts_with("--") => out = Some(arg),
+            _ => usage(),
+        }
+    }
+    let out = match out {
+        Some(o) => o,
+        None    => usage(),
+    };
+
+    let vfs = vfs::default(asset_dir.as_deref(), None);
+    let mut baked = Vec::new();
+    for &name in files {
+        if !vfs.exists(name) {
+            info!("skipping {}, not found", name);
+            continue;
+        }
+        let data = if name.ends_with(".bmp") {
+            match loader::decode_image(&vfs, name) {
+                Ok(image) => image.bake(),
+                Err(err)  => panic!("{}", err),
+            }
+        } else {
+            match vfs.read(name) {
+                Ok(d)    => d.into_owned(),
+                Err(err) => panic!("couldn't read {}: {}", name, err),
+            }
+        };
+        info!("baked {}, {} bytes", name, data.len());
+        baked.push((name, data));
+    }
+
+    match Pak::write(&out, &baked) {
+        Ok(())   => info!("wrote {} files to {}", baked.len(), out),
+        Err(err) => panic!("couldn't write {}: {}", out, err),
+    }
+}
@@ -0,0 +1,419 @@
+use gleam::gl;
+use gleam::gl::types::{GLuint, GLuint64, GLenum, GLbitfield, GLsizeiptr, GLsync};
+use sdl2::sys;
+use std::ffi::CString;
+use std::fmt;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::OnceLock;
+
+use GlPtr;
+use caps::Capabilities;
+use label::{self, Object};
+use memory::{self, Category};
+use state::StateCache;
+
+// number of regions the fenced strategies cycle through. three is
+// enough to keep the CPU one frame ahead of a GPU that's one frame behind.
+const REGIONS: usize = 3;
+
+// how long to wait for the GPU to release a region before complaining and
+// waiting again, in ns
+const FENCE_TIMEOUT: u64 = 1_000_000_000;
+
+// only a hint to the driver. per-frame data that doesn't need a buffer of
+// its own goes through StreamBuffer instead
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Usage {
+    // written once, drawn many times
+    Static,
+    // rewritten now and then, drawn many times
+    #[cfg_attr(not(test), allow(dead_code))]
+    Dynamic,
+    // rewritten about as often as it's drawn
+    #[cfg_attr(not(test), allow(dead_code))]
+    Stream,
+}
+
+impl Usage {
+    fn gl_usage(self) -> GLenum {
+        match self {
+            Usage::Static  => gl::STATIC_DRAW,
+            Usage::Dynamic => gl::DYNAMIC_DRAW,
+            Usage::Stream  => gl::STREAM_DRAW,
+        }
+    }
+
+    fn category(self) -> Category {
+        match self {
+            Usage::Static  => Category::StaticBuffers,
+            Usage::Dynamic => Category::DynamicBuffers,
+            Usage::Stream  => Category::StreamBuffers,
+        }
+    }
+}
+
+fn gen_buffer(gl: &GlPtr) -> GLuint {
+    let buffers = gl.gen_buffers(1);
+    match buffers.len() {
+        0 => panic!("couldn't create buffer"),
+        _ => buffers[0],
+    }
+}
+
+pub struct Buffer {
+    gl: GlPtr,
+    pub buffer: GLuint,
+    target: GLenum,
+    size: usize,
+    usage: Usage,
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Buffer {{ buffer: {}, target: 0x{:x}, size: {}, usage: {:?} }}", self.buffer, self.target, self.size, self.usage)
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.gl.delete_buffers(&[self.buffer]);
+        memory::freed(self.usage.category(), self.size);
+    }
+}
+
+impl Buffer {
+    pub fn new<T>(gl: &GlPtr, target: GLenum, usage: Usage, data: &[T]) -> Buffer {
+        let size = mem::size_of_val(data);
+        let _span = trace_span!("buffer upload", bytes = size).entered();
+        let buffer = gen_buffer(gl);
+        gl.bind_buffer(target, buffer);
+        gl.buffer_data_untyped(target, size as GLsizeiptr, data.as_ptr() as *const _, usage.gl_usage());
+        memory::allocated(usage.category(), size);
+        Buffer {
+            gl: gl.clone(),
+            buffer,
+            target,
+            size,
+            usage,
+        }
+    }
+
+    pub fn labeled(self, name: &str) -> Buffer {
+        label::object(Object::Buffer, self.buffer, name);
+        self
+    }
+
+    // overwrites part of the buffer, starting offset bytes in. the buffer
+    // keeps its size; use a new one to grow.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn update<T>(&mut self, state: &mut StateCache, offset: usize, data: &[T]) {
+        let len = mem::size_of_val(data);
+        let _span = trace_span!("buffer update", bytes = len).entered();
+        if offset.checked_add(len).is_none_or(|end| end > self.size) {
+            panic!("buffer update of {} bytes at {} overruns buffer of {}", len, offset, self.size);
+        }
+        state.bind_buffer(self.target, self.buffer);
+        self.gl.buffer_sub_data_untyped(self.target, offset as isize, len as GLsizeiptr, data.as_ptr() as *const _);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    // respecify the whole store every upload, letting the driver hand us a
+    // fresh one while the GPU still reads the old. works everywhere,
+    // including GLES2.
+    Orphan,
+    // carve the buffer into regions and write each one through an
+    // unsynchronized mapping, guarded by a fence so we never touch a region
+    // the GPU is still reading. needs sync objects and map_buffer_range.
+    Synchronized,
+    // the same regions and fences, but the store is made with
+    // glBufferStorage and mapped once, coherently, for its whole life, so a
+    // push is only a copy. needs GL 4.4 or ARB/EXT_buffer_storage.
+    Persistent,
+}
+
+impl Strategy {
+    pub fn select(caps: &Capabilities) -> Strategy {
+        let fns = Fns::get();
+        let fenced = caps.sync_objects() && caps.map_buffer_range() && fns.client_wait_sync.is_some();
+        let strategy = if fenced && caps.buffer_storage() && fns.buffer_storage.is_some() {
+            Strategy::Persistent
+        } else if fenced {
+            Strategy::Synchronized
+        } else {
+            Strategy::Orphan
+        };
+        debug!("streaming buffers use the {:?} strategy", strategy);
+        strategy
+    }
+
+    // bytes of store behind `size` bytes of usable space
+    fn store_size(self, size: usize) -> usize {
+        match self {
+            Strategy::Orphan       => size,
+            Strategy::Synchronized => size * REGIONS,
+            Strategy::Persistent   => size * REGIONS,
+        }
+    }
+}
+
+// GL_ARB_buffer_storage
+const MAP_PERSISTENT_BIT: GLbitfield = 0x0040;
+const MAP_COHERENT_BIT: GLbitfield = 0x0080;
+
+type ClientWaitSyncFn = extern "system" fn(GLsync, GLbitfield, GLuint64) -> GLenum;
+type BufferStorageFn = extern "system" fn(GLenum, GLsizeiptr, *const c_void, GLbitfield);
+
+// gleam's client_wait_sync throws away whether the wait succeeded, and it
+// has no glBufferStorage, so these are looked up directly. looked up once,
+// the first time a context is current.
+struct Fns {
+    client_wait_sync: Option<ClientWaitSyncFn>,
+    buffer_storage: Option<BufferStorageFn>,
+}
+
+static FNS: OnceLock<Fns> = OnceLock::new();
+
+fn lookup(names: &[&str]) -> Option<*mut c_void> {
+    names.iter().map(|name| {
+        let name = CString::new(*name).unwrap();
+        unsafe { sys::SDL_GL_GetProcAddress(name.as_ptr()) }
+    }).find(|f| !f.is_null())
+}
+
+impl Fns {
+    fn get() -> &'static Fns {
+        FNS.get_or_init(|| unsafe {
+            Fns {
+                client_wait_sync: lookup(&["glClientWaitSync"])
+                    .map(|f| mem::transmute::<*mut c_void, ClientWaitSyncFn>(f)),
+                buffer_storage: lookup(&["glBufferStorage", "glBufferStorageEXT"])
+                    .map(|f| mem::transmute::<*mut c_void, BufferStorageFn>(f)),
+            }
+        })
+    }
+}
+
+// blocks until the GPU has passed fence, then deletes it. a wait that
+// times out is retried: a slow frame isn't an error, but reusing the
+// region before it's done would be.
+fn wait_fence(gl: &GlPtr, fence: GLsync) {
+    let wait = match Fns::get().client_wait_sync {
+        Some(f) => f,
+        None    => panic!("fenced stream buffer without glClientWaitSync"),
+    };
+    let mut flags = gl::SYNC_FLUSH_COMMANDS_BIT;
+    loop {
+        match wait(fence, flags, FENCE_TIMEOUT) {
+            gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => break,
+            gl::TIMEOUT_EXPIRED => {
+                warn!("still waiting for the GPU to release a stream buffer region");
+                // the commands were flushed by the first wait
+                flags = 0;
+            },
+            _ => panic!("waiting for a stream buffer fence failed: {}", gl.get_error()),
+        }
+    }
+    gl.delete_sync(fence);
+}
+
+// one big buffer that transient per-frame data (vertices, uniforms) is
+// sub-allocated from, so every system pushing data each frame shares a
+// single upload path. the store is split into a segment per frame in
+// flight; finishing a frame fences its segment and moves on to the next.
+pub struct StreamBuffer {
+    gl: GlPtr,
+    pub buffer: GLuint,
+    target: GLenum,
+    segment: usize,
+    strategy: Strategy,
+    // the whole store, for Strategy::Persistent; null otherwise
+    mapped: *mut u8,
+    frame: usize,
+    cursor: usize,
+    fences: [Option<GLsync>; REGIONS],
+}
+
+// allocations are aligned to this, enough for any vertex attribute or
+// uniform data we push
+const STREAM_ALIGN: usize = 16;
+
+impl StreamBuffer {
+    pub fn new(gl: &GlPtr, caps: &Capabilities, target: GLenum, segment: usize) -> StreamBuffer {
+        let strategy = Strategy::select(caps);
+        let buffer = gen_buffer(gl);
+        let store = strategy.store_size(segment);
+        gl.bind_buffer(target, buffer);
+
+        let mapped = if strategy == Strategy::Persistent {
+            let flags = gl::MAP_WRITE_BIT | MAP_PERSISTENT_BIT | MAP_COHERENT_BIT;
+            let storage = match Fns::get().buffer_storage {
+                Some(f) => f,
+                None    => panic!("persistent stream buffer without glBufferStorage"),
+            };
+            storage(target, store as GLsizeiptr, ptr::null(), flags);
+            let dst = gl.map_buffer_range(target, 0, store as GLsizeiptr, flags);
+            if dst.is_null() {
+                panic!("couldn't map stream buffer: {}", gl.get_error());
+            }
+            dst as *mut u8
+        } else {
+            gl.buffer_data_untyped(target, store as GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+            ptr::null_mut()
+        };
+        memory::allocated(Category::StreamBuffers, store);
+
+        StreamBuffer {
+            gl: gl.clone(),
+            buffer,
+            target,
+            segment,
+            strategy,
+            mapped,
+            frame: 0,
+            cursor: 0,
+            fences: [None; REGIONS],
+        }
+    }
+
+    pub fn labeled(self, name: &str) -> StreamBuffer {
+        label::object(Object::Buffer, self.buffer, name);
+        self
+    }
+
+    // copies data into this frame's segment and returns its byte offset
+    // within the buffer, which is left bound. the data is valid until the
+    // frame is finished.
+    pub fn push<T>(&mut self, state: &mut StateCache, data: &[T]) -> usize {
+        let gl = &self.gl;
+        let len = mem::size_of_val(data);
+        let _span = trace_span!("stream push", bytes = len).entered();
+        let start = (self.cursor + STREAM_ALIGN - 1) & !(STREAM_ALIGN - 1);
+        state.bind_buffer(self.target, self.buffer);
+
+        match self.strategy {
+            Strategy::Orphan => {
+                if len > self.segment {
+                    panic!("stream buffer push of {} bytes exceeds segment of {}", len, self.segment);
+                }
+                // out of room: hand the old store back to the driver and
+                // start filling a fresh one
+                let start = if start + len > self.segment {
+                    gl.buffer_data_untyped(self.target, self.segment as GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+                    0
+                } else {
+                    start
+                };
+                gl.buffer_sub_data_untyped(self.target, start as isize, len as GLsizeiptr, data.as_ptr() as *const _);
+                self.cursor = start + len;
+                start
+            },
+
+            Strategy::Synchronized | Strategy::Persistent => {
+                if start + len > self.segment {
+                    panic!("stream buffer frame overflow: {} bytes pushed into segment of {}", start + len, self.segment);
+                }
+                let offset = self.frame * self.segment + start;
+                if self.strategy == Strategy::Persistent {
+                    unsafe {
+                        ptr::copy_nonoverlapping(data.as_ptr() as *const u8, self.mapped.add(offset), len);
+                    }
+                } else {
+                    let dst = gl.map_buffer_range(
+                        self.target,
+                        offset as isize, len as GLsizeiptr,
+                        gl::MAP_WRITE_BIT | gl::MAP_UNSYNCHRONIZED_BIT | gl::MAP_INVALIDATE_RANGE_BIT
+                    );
+                    if dst.is_null() {
+                        panic!("couldn't map stream buffer: {}", gl.get_error());
+                    }
+                    unsafe {
+                        ptr::copy_nonoverlapping(data.as_ptr() as *const u8, dst as *mut u8, len);
+                    }
+                    gl.unmap_buffer(self.target);
+                }
+                self.cursor = start + len;
+                offset
+            },
+        }
+    }
+
+    // call after the frame's draws are issued. fences the segment just used
+    // and waits for the next one to come free, or orphans the store.
+    pub fn finish_frame(&mut self, state: &mut StateCache) {
+        let gl = &self.gl;
+        match self.strategy {
+            Strategy::Orphan => {
+                state.bind_buffer(self.target, self.buffer);
+                gl.buffer_data_untyped(self.target, self.segment as GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+            },
+
+            Strategy::Synchronized | Strategy::Persistent => {
+                self.fences[self.frame] = Some(gl.fence_sync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+                self.frame = (self.frame + 1) % REGIONS;
+                if let Some(fence) = self.fences[self.frame].take() {
+                    wait_fence(gl, fence);
+                }
+            },
+        }
+        self.cursor = 0;
+    }
+}
+
+// fences still pending are deleted along with the buffer, which also
+// unmaps a persistent store; the GPU keeps the store alive until it's done
+// with it
+impl Drop for StreamBuffer {
+    fn drop(&mut self) {
+        for fence in self.fences.iter_mut() {
+            if let Some(f) = fence.take() {
+                self.gl.delete_sync(f);
+            }
+        }
+        self.gl.delete_buffers(&[self.buffer]);
+        memory::freed(Category::StreamBuffers, self.strategy.store_size(self.segment));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_gl;
+
+    #[test]
+    fn update_binds_through_the_cache() {
+        let gl = fake_gl::load();
+        let mut state = StateCache::new(&gl, &fake_gl::caps());
+        let mut buffer = Buffer::new(&gl, gl::ARRAY_BUFFER, Usage::Dynamic, &[0u8; 16]);
+        let name = buffer.buffer;
+        fake_gl::take();
+
+        buffer.update(&mut state, 4, &[1u8; 8]);
+        buffer.update(&mut state, 12, &[2u16; 2]);
+        assert_eq!(fake_gl::take(), vec![
+            format!("glBindBuffer(0x8892, {})", name),
+            "glBufferSubData(0x8892, 4, 8)".to_string(),
+            "glBufferSubData(0x8892, 12, 4)".to_string(),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "overruns")]
+    fn update_past_the_end() {
+        let gl = fake_gl::load();
+        let mut state = StateCache::new(&gl, &fake_gl::caps());
+        let mut buffer = Buffer::new(&gl, gl::ARRAY_BUFFER, Usage::Dynamic, &[0u8; 16]);
+        buffer.update(&mut state, 12, &[0u8; 8]);
+    }
+
+    #[test]
+    fn usage_categories() {
+        assert_eq!(Usage::Static.category(), Category::StaticBuffers);
+        assert_eq!(Usage::Dynamic.category(), Category::DynamicBuffers);
+        assert_eq!(Usage::Stream.category(), Category::StreamBuffers);
+        assert_eq!(Usage::Stream.gl_usage(), gl::STREAM_DRAW);
+    }
+}
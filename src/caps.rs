@@ -0,0 +1,136 @@
+use gleam::gl;
+use gleam::gl::types::{GLint, GLuint, GLenum};
+use std::collections::HashSet;
+
+use GlPtr;
+use vertex::HALF_FLOAT_OES;
+
+// what the context can do, queried once after it's created. optional
+// features ask here rather than poking at version and extension strings
+// themselves, so they all degrade the same way.
+pub struct Capabilities {
+    pub es: bool,
+    pub version: (u32, u32),
+    pub glsl_version: (u32, u32),
+    pub extensions: HashSet<String>,
+    pub max_texture_size: GLint,
+    pub max_texture_units: GLint,
+    pub max_vertex_attribs: GLint,
+}
+
+// pulls "major.minor" out of strings like "4.6.0 NVIDIA 535.54",
+// "OpenGL ES 3.2 Mesa 23.1" or "OpenGL ES GLSL ES 3.00"
+fn parse_version(s: &str) -> (u32, u32) {
+    let mut nums = s.split(|c: char| !c.is_ascii_digit()).filter_map(|n| n.parse::<u32>().ok());
+    let major = nums.next().unwrap_or(0);
+    let minor = nums.next().unwrap_or(0);
+    // GLSL spells minor versions as two digits
+    (major, if minor >= 10 { minor / 10 } else { minor })
+}
+
+impl Capabilities {
+    pub fn detect(gl: &GlPtr) -> Capabilities {
+        let version = gl.get_string(gl::VERSION);
+
+        let all = gl.get_string(gl::EXTENSIONS);
+        let extensions = if !all.is_empty() {
+            all.split_whitespace().map(|s| s.to_string()).collect()
+        } else {
+            // core profiles only answer the indexed query
+            let count = gl.get_integer_v(gl::NUM_EXTENSIONS);
+            (0..count).map(|i| gl.get_string_i(gl::EXTENSIONS, i as GLuint)).collect()
+        };
+
+        Capabilities {
+            es: version.contains("OpenGL ES"),
+            version: parse_version(&version),
+            glsl_version: parse_version(&gl.get_string(gl::SHADING_LANGUAGE_VERSION)),
+            extensions,
+            max_texture_size: gl.get_integer_v(gl::MAX_TEXTURE_SIZE),
+            max_texture_units: gl.get_integer_v(gl::MAX_TEXTURE_IMAGE_UNITS),
+            max_vertex_attribs: gl.get_integer_v(gl::MAX_VERTEX_ATTRIBS),
+        }
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.contains(name)
+    }
+
+    // true if the context is at least the given desktop or ES version,
+    // whichever applies
+    pub fn at_least(&self, gl: (u32, u32), es: (u32, u32)) -> bool {
+        self.version >= if self.es { es } else { gl }
+    }
+
+    pub fn sync_objects(&self) -> bool {
+        self.at_least((3, 2), (3, 0)) || self.has_extension("GL_ARB_sync")
+    }
+
+    pub fn map_buffer_range(&self) -> bool {
+        self.at_least((3, 0), (3, 0))
+            || self.has_extension("GL_ARB_map_buffer_range")
+            || self.has_extension("GL_EXT_map_buffer_range")
+    }
+
+    pub fn buffer_storage(&self) -> bool {
+        self.at_least((4, 4), (99, 0))
+            || self.has_extension("GL_ARB_buffer_storage")
+            || self.has_extension("GL_EXT_buffer_storage")
+    }
+
+    pub fn packed_vertex_formats(&self) -> bool {
+        self.at_least((3, 3), (3, 0)) || self.has_extension("GL_ARB_vertex_type_2_10_10_10_rev")
+    }
+
+    // the type enum to source half-float attributes with, if any
+    pub fn half_float_vertices(&self) -> Option<GLenum> {
+        if self.at_least((3, 0), (3, 0)) || self.has_extension("GL_ARB_half_float_vertex") {
+            Some(gl::HALF_FLOAT)
+        } else if self.has_extension("GL_OES_vertex_half_float") {
+            Some(HALF_FLOAT_OES)
+        } else {
+            None
+        }
+    }
+
+    pub fn sampler_objects(&self) -> bool {
+        self.at_least((3, 3), (3, 0)) || self.has_extension("GL_ARB_sampler_objects")
+    }
+
+    pub fn anisotropic_filtering(&self) -> bool {
+        self.at_least((4, 6), (99, 0))
+            || self.has_extension("GL_EXT_texture_filter_anisotropic")
+            || self.has_extension("GL_ARB_texture_filter_anisotropic")
+    }
+
+    pub fn point_size_needs_enable(&self) -> bool {
+        !self.es
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert_eq!(parse_version("4.6.0 NVIDIA 535.1"), (4, 6));
+        assert_eq!(parse_version("OpenGL ES 3.0 Mesa 23.1"), (3, 0));
+        assert_eq!(parse_version("OpenGL ES 3.2 Mesa 23.1"), (3, 2));
+    }
+
+    #[test]
+    fn glsl_versions() {
+        assert_eq!(parse_version("OpenGL ES GLSL ES 3.00"), (3, 0));
+        assert_eq!(parse_version("OpenGL ES GLSL ES 1.00"), (1, 0));
+        assert_eq!(parse_version("3.30"), (3, 3));
+        assert_eq!(parse_version("4.60 NVIDIA"), (4, 6));
+        assert_eq!(parse_version("1.20"), (1, 2));
+    }
+
+    #[test]
+    fn no_version() {
+        assert_eq!(parse_version(""), (0, 0));
+        assert_eq!(parse_version("unknown"), (0, 0));
+    }
+}
@@ -0,0 +1,72 @@
+use gleam::gl::types::GLfloat;
+
+// after a long stall, give up on catching up past this many ticks rather
+// than spending the next frames simulating the backlog
+const MAX_TICKS_PER_FRAME: u32 = 5;
+
+// a fixed-rate simulation clock. each frame, advance() says how many ticks
+// of simulation to run for the time that passed; whatever is left over, as
+// a fraction of a tick, is alpha() for blending the last two ticks' state.
+pub struct FixedStep {
+    tick_ms: u32,
+    last_ms: u32,
+    accumulator: u32,
+    ticks: u64,
+}
+
+impl FixedStep {
+    pub fn new(tick_ms: u32, now_ms: u32) -> FixedStep {
+        FixedStep {
+            tick_ms,
+            last_ms: now_ms,
+            accumulator: 0,
+            ticks: 0,
+        }
+    }
+
+    pub fn advance(&mut self, now_ms: u32) -> u32 {
+        self.accumulator += now_ms.wrapping_sub(self.last_ms);
+        self.last_ms = now_ms;
+
+        let mut n = self.accumulator / self.tick_ms;
+        if n > MAX_TICKS_PER_FRAME {
+            n = MAX_TICKS_PER_FRAME;
+            self.accumulator = n * self.tick_ms;
+        }
+        self.accumulator -= n * self.tick_ms;
+        self.ticks += n as u64;
+        n
+    }
+
+    // simulated time at the latest tick
+    pub fn time_ms(&self) -> u64 {
+        self.ticks * self.tick_ms as u64
+    }
+
+    pub fn alpha(&self) -> GLfloat {
+        self.accumulator as GLfloat / self.tick_ms as GLfloat
+    }
+}
+
+// a value as of the last two ticks, for rendering in between them
+#[derive(Clone, Copy, Debug)]
+pub struct Interpolated {
+    previous: GLfloat,
+    current: GLfloat,
+}
+
+impl Interpolated {
+    pub fn new(value: GLfloat) -> Interpolated {
+        Interpolated { previous: value, current: value }
+    }
+
+    // the value for the tick just run
+    pub fn set(&mut self, value: GLfloat) {
+        self.previous = self.current;
+        self.current = value;
+    }
+
+    pub fn at(&self, alpha: GLfloat) -> GLfloat {
+        self.previous + (self.current - self.previous) * alpha
+    }
+}
@@ -0,0 +1,218 @@
+use gleam::gl::types::GLfloat;
+
+use geom::{Vec3, add, sub, scale, dot};
+
+// as in geom, what the demo doesn't use yet is only called from the tests
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: GLfloat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    // need not be normalized; hit distances are in units of its length
+    pub direction: Vec3,
+}
+
+impl Aabb {
+    // bounds of the first three components of each vertex in an interleaved
+    // float array, which is where meshes keep their positions. 2D vertices
+    // are taken to lie in the z=0 plane.
+    pub fn from_vertices(vertices: &[GLfloat], vertex_size: usize) -> Aabb {
+        let mut min = [GLfloat::INFINITY; 3];
+        let mut max = [GLfloat::NEG_INFINITY; 3];
+        for v in vertices.chunks(vertex_size) {
+            for i in 0..3 {
+                let c = v.get(i).cloned().unwrap_or(0.0);
+                min[i] = min[i].min(c);
+                max[i] = max[i].max(c);
+            }
+        }
+        Aabb { min, max }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn center(&self) -> Vec3 {
+        [
+            (self.min[0] + self.max[0]) * 0.5,
+            (self.min[1] + self.max[1]) * 0.5,
+            (self.min[2] + self.max[2]) * 0.5,
+        ]
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn contains(&self, p: Vec3) -> bool {
+        (0..3).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i])
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn closest_point(&self, p: Vec3) -> Vec3 {
+        [
+            p[0].max(self.min[0]).min(self.max[0]),
+            p[1].max(self.min[1]).min(self.max[1]),
+            p[2].max(self.min[2]).min(self.max[2]),
+        ]
+    }
+
+    // loose sphere around the box; cheap, not minimal
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn bounding_sphere(&self) -> Sphere {
+        let c = self.center();
+        let d = sub(self.max, c);
+        Sphere { center: c, radius: dot(d, d).sqrt() }
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Sphere {
+    pub fn intersects(&self, other: &Sphere) -> bool {
+        let d = sub(self.center, other.center);
+        let r = self.radius + other.radius;
+        dot(d, d) <= r * r
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let d = sub(aabb.closest_point(self.center), self.center);
+        dot(d, d) <= self.radius * self.radius
+    }
+}
+
+impl Ray {
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn at(&self, t: GLfloat) -> Vec3 {
+        add(self.origin, scale(self.direction, t))
+    }
+
+    // distance along the ray to where it enters the box, 0 if it starts
+    // inside, None on a miss. slab test; infinities from axis-parallel
+    // directions fall out of the min/max correctly.
+    pub fn hit_aabb(&self, aabb: &Aabb) -> Option<GLfloat> {
+        let mut near: GLfloat = 0.0;
+        let mut far = GLfloat::INFINITY;
+        for i in 0..3 {
+            let inv = 1.0 / self.direction[i];
+            let mut t0 = (aabb.min[i] - self.origin[i]) * inv;
+            let mut t1 = (aabb.max[i] - self.origin[i]) * inv;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            near = near.max(t0);
+            far = far.min(t1);
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    // distance along the ray to the sphere's surface, 0 if it starts inside
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn hit_sphere(&self, sphere: &Sphere) -> Option<GLfloat> {
+        let oc = sub(self.origin, sphere.center);
+        let a = dot(self.direction, self.direction);
+        let b = dot(oc, self.direction);
+        let c = dot(oc, oc) - sphere.radius * sphere.radius;
+        if c <= 0.0 {
+            return Some(0.0);
+        }
+        let disc = b * b - a * c;
+        if disc < 0.0 || b > 0.0 {
+            return None;
+        }
+        Some((-b - disc.sqrt()) / a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIT: Aabb = Aabb { min: [-1.0, -1.0, -1.0], max: [1.0, 1.0, 1.0] };
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray {
+        Ray { origin, direction }
+    }
+
+    #[test]
+    fn aabb_from_vertices() {
+        let aabb = Aabb::from_vertices(&[1.0, -2.0, 3.0, 9.0,  -1.0, 2.0, 0.0, 9.0], 4);
+        assert_eq!(aabb, Aabb { min: [-1.0, -2.0, 0.0], max: [1.0, 2.0, 3.0] });
+        // 2D vertices lie flat at z=0
+        let aabb = Aabb::from_vertices(&[1.0, -2.0,  -1.0, 2.0], 2);
+        assert_eq!(aabb, Aabb { min: [-1.0, -2.0, 0.0], max: [1.0, 2.0, 0.0] });
+    }
+
+    #[test]
+    fn aabb_queries() {
+        assert_eq!(UNIT.center(), [0.0, 0.0, 0.0]);
+        assert!(UNIT.contains([1.0, 0.0, -1.0]));
+        assert!(!UNIT.contains([1.1, 0.0, 0.0]));
+        assert!(UNIT.intersects(&Aabb { min: [1.0, 0.0, 0.0], max: [2.0, 2.0, 2.0] }));
+        assert!(!UNIT.intersects(&Aabb { min: [1.5, 0.0, 0.0], max: [2.0, 2.0, 2.0] }));
+        assert_eq!(UNIT.closest_point([3.0, 0.5, -3.0]), [1.0, 0.5, -1.0]);
+        assert_eq!(UNIT.bounding_sphere(), Sphere { center: [0.0, 0.0, 0.0], radius: 3.0f32.sqrt() });
+    }
+
+    #[test]
+    fn sphere_queries() {
+        let s = Sphere { center: [0.0, 0.0, 0.0], radius: 1.0 };
+        assert!(s.intersects(&Sphere { center: [2.0, 0.0, 0.0], radius: 1.0 }));
+        assert!(!s.intersects(&Sphere { center: [2.1, 0.0, 0.0], radius: 1.0 }));
+        assert!(s.intersects_aabb(&Aabb { min: [0.5, 0.5, -1.0], max: [2.0, 2.0, 1.0] }));
+        // the box's corner is sqrt(2) * 0.8 away
+        assert!(!s.intersects_aabb(&Aabb { min: [0.8, 0.8, -1.0], max: [2.0, 2.0, 1.0] }));
+    }
+
+    #[test]
+    fn ray_hits_aabb() {
+        assert_eq!(ray([-5.0, -5.0, -5.0], [1.0, 1.0, 1.0]).hit_aabb(&UNIT), Some(4.0));
+        assert_eq!(ray([5.0, 0.5, 0.5], [-2.0, 0.0, 0.0]).hit_aabb(&UNIT), Some(2.0));
+        // starting inside
+        assert_eq!(ray([0.5, 0.0, 0.0], [0.0, 1.0, 0.0]).hit_aabb(&UNIT), Some(0.0));
+        // pointing away, and passing by
+        assert_eq!(ray([-5.0, 0.0, 0.0], [-1.0, 0.0, 0.0]).hit_aabb(&UNIT), None);
+        assert_eq!(ray([-5.0, 0.0, 0.0], [1.0, 1.0, 0.0]).hit_aabb(&UNIT), None);
+    }
+
+    #[test]
+    fn ray_hits_aabb_axis_parallel() {
+        // the zero components give infinite slab distances
+        assert_eq!(ray([-5.0, 0.5, -0.5], [1.0, 0.0, 0.0]).hit_aabb(&UNIT), Some(4.0));
+        assert_eq!(ray([0.0, 0.0, 5.0], [0.0, 0.0, -1.0]).hit_aabb(&UNIT), Some(4.0));
+        assert_eq!(ray([-5.0, 2.0, 0.0], [1.0, 0.0, 0.0]).hit_aabb(&UNIT), None);
+        assert_eq!(ray([-5.0, 0.0, -2.0], [1.0, 0.0, 0.0]).hit_aabb(&UNIT), None);
+        // along a face: 0 * inf is NaN, which min and max pass over, so
+        // it counts as inside that slab
+        assert_eq!(ray([-5.0, 1.0, 0.0], [1.0, 0.0, 0.0]).hit_aabb(&UNIT), Some(4.0));
+        // through a flat box, as when picking the quad
+        let flat = Aabb { min: [-1.0, -1.0, 0.0], max: [1.0, 1.0, 0.0] };
+        assert_eq!(ray([0.3, -0.7, -1.0], [0.0, 0.0, 1.0]).hit_aabb(&flat), Some(1.0));
+        assert_eq!(ray([1.3, -0.7, -1.0], [0.0, 0.0, 1.0]).hit_aabb(&flat), None);
+    }
+
+    #[test]
+    fn ray_hits_sphere() {
+        let s = Sphere { center: [0.0, 0.0, 0.0], radius: 1.0 };
+        assert_eq!(ray([-5.0, 0.0, 0.0], [1.0, 0.0, 0.0]).hit_sphere(&s), Some(4.0));
+        assert_eq!(ray([-5.0, 0.0, 0.0], [2.0, 0.0, 0.0]).hit_sphere(&s), Some(2.0));
+        assert_eq!(ray([0.5, 0.0, 0.0], [1.0, 0.0, 0.0]).hit_sphere(&s), Some(0.0));
+        assert_eq!(ray([-5.0, 1.5, 0.0], [1.0, 0.0, 0.0]).hit_sphere(&s), None);
+        assert_eq!(ray([5.0, 0.0, 0.0], [1.0, 0.0, 0.0]).hit_sphere(&s), None);
+        assert_eq!(ray([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]).at(2.5), [2.5, 0.0, 0.0]);
+    }
+}
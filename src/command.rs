@@ -0,0 +1,99 @@
+use gleam::gl::types::{GLuint, GLint, GLenum, GLfloat, GLsizei};
+use std::fmt;
+
+use buffer::Buffer;
+use pipeline::PipelineState;
+use sampler::Sampler;
+use state::StateCache;
+use vertex::VertexLayout;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Uniform {
+    Float(GLfloat),
+    Vec2([GLfloat; 2]),
+    Vec3([GLfloat; 3]),
+    Vec4([GLfloat; 4]),
+    Mat3([GLfloat; 9]),
+    Mat4([GLfloat; 16]),
+    Int(GLint),
+}
+
+impl Uniform {
+    // to the currently bound program
+    pub fn apply(&self, state: &mut StateCache, location: GLint) {
+        match *self {
+            Uniform::Float(v) => state.uniform_1f(location, v),
+            Uniform::Vec2(v)  => state.uniform_2f(location, v[0], v[1]),
+            Uniform::Vec3(v)  => state.uniform_3f(location, v[0], v[1], v[2]),
+            Uniform::Vec4(v)  => state.uniform_4f(location, v[0], v[1], v[2], v[3]),
+            Uniform::Mat3(ref m) => state.uniform_matrix_3fv(location, false, m),
+            Uniform::Mat4(ref m) => state.uniform_matrix_4fv(location, false, m),
+            Uniform::Int(v)   => state.uniform_1i(location, v),
+        }
+    }
+}
+
+// one step of a frame, recorded by the scene code and replayed by a
+// backend. keeping the frame as data means it can be printed, compared
+// between runs, or fed to something other than GL.
+#[derive(Clone, Debug)]
+pub enum RenderCommand<'a> {
+    SetPipeline(PipelineState),
+    SetProgram(GLuint),
+    SetUniform(GLint, Uniform),
+    BindSampler { location: GLint, texture: GLuint, sampler: Sampler },
+    BindVertexBuffers { layout: &'a VertexLayout, buffers: &'a [Buffer] },
+    BindIndexBuffer(&'a Buffer),
+    DrawElements { mode: GLenum, count: GLsizei, typ: GLenum },
+}
+
+#[derive(Default)]
+pub struct CommandBuffer<'a> {
+    commands: Vec<RenderCommand<'a>>,
+}
+
+impl<'a> CommandBuffer<'a> {
+    pub fn new() -> CommandBuffer<'a> {
+        CommandBuffer {
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, command: RenderCommand<'a>) {
+        self.commands.push(command);
+    }
+
+    pub fn execute(&self, state: &mut StateCache) {
+        use gleam::gl;
+
+        for command in &self.commands {
+            match *command {
+                RenderCommand::SetPipeline(ref pipeline) => state.set_pipeline(pipeline),
+
+                RenderCommand::SetProgram(program) => state.use_program(program),
+
+                RenderCommand::SetUniform(location, uniform) => uniform.apply(state, location),
+
+                RenderCommand::BindSampler { location, texture, sampler } => state.bind_sampler(location, texture, sampler),
+
+                RenderCommand::BindVertexBuffers { layout, buffers } => layout.bind(state, buffers),
+
+                RenderCommand::BindIndexBuffer(buffer) => state.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, buffer.buffer),
+
+                RenderCommand::DrawElements { mode, count, typ } => {
+                    state.draw_elements(mode, count, typ, 0);
+                    state.end_draw();
+                },
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Display for CommandBuffer<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, command) in self.commands.iter().enumerate() {
+            writeln!(f, "{:4} {:?}", i, command)?;
+        }
+        Ok(())
+    }
+}
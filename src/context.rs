@@ -0,0 +1,190 @@
+use sdl2::VideoSubsystem;
+use sdl2::video::{GLContext, GLProfile, Window, WindowBuilder};
+use std::fmt;
+
+// what we ask the window system for. anything it can't provide is lowered
+// one rung at a time until a context comes up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    // major version of the GLES context to ask for, None for desktop GL
+    pub es: Option<u8>,
+    // MSAA samples, 0 for none
+    pub samples: u8,
+    pub srgb: bool,
+    pub depth: u8,
+    pub stencil: u8,
+}
+
+impl Config {
+    fn apply(&self, video: &VideoSubsystem) {
+        let gl_attr = video.gl_attr();
+        if let Some(major) = self.es {
+            gl_attr.set_context_profile(GLProfile::GLES);
+            gl_attr.set_context_version(major, 0);
+        }
+        gl_attr.set_red_size(8);
+        gl_attr.set_green_size(8);
+        gl_attr.set_blue_size(8);
+        gl_attr.set_depth_size(self.depth);
+        gl_attr.set_stencil_size(self.stencil);
+        gl_attr.set_multisample_buffers(if self.samples > 0 { 1 } else { 0 });
+        gl_attr.set_multisample_samples(self.samples);
+        gl_attr.set_framebuffer_srgb_compatible(self.srgb);
+        gl_attr.set_double_buffer(true);
+    }
+
+    // the next rung down, with what was given up, or None at the bottom.
+    // least noticeable losses go first. the shaders are rewritten for
+    // whichever GLES version we get, so dropping to GLES 2 leads.
+    fn degrade(&self) -> Option<(Config, String)> {
+        if self.es > Some(2) {
+            Some((Config { es: Some(2), ..*self }, "GLES 3 context unavailable, trying GLES 2".to_string()))
+        } else if self.samples > 0 {
+            let samples = if self.samples > 2 { self.samples / 2 } else { 0 };
+            Some((Config { samples, ..*self },
+                  format!("{}x MSAA unavailable, trying {}", self.samples, samples)))
+        } else if self.srgb {
+            Some((Config { srgb: false, ..*self }, "sRGB framebuffer unavailable".to_string()))
+        } else if self.stencil > 0 {
+            Some((Config { stencil: 0, ..*self }, "stencil buffer unavailable".to_string()))
+        } else if self.depth > 16 {
+            Some((Config { depth: 16, ..*self },
+                  format!("{}-bit depth buffer unavailable, trying 16", self.depth)))
+        } else {
+            None
+        }
+    }
+
+    // what the current context actually got
+    fn current(video: &VideoSubsystem) -> Config {
+        let gl_attr = video.gl_attr();
+        Config {
+            es: match gl_attr.context_profile() {
+                GLProfile::GLES => Some(gl_attr.context_major_version()),
+                _               => None,
+            },
+            samples: if gl_attr.multisample_buffers() > 0 { gl_attr.multisample_samples() } else { 0 },
+            srgb: gl_attr.framebuffer_srgb_compatible(),
+            depth: gl_attr.depth_size(),
+            stencil: gl_attr.stencil_size(),
+        }
+    }
+}
+
+// what we ended up with, and what was given up on the way
+pub struct Report {
+    pub actual: Config,
+    pub downgrades: Vec<String>,
+}
+
+impl Report {
+    pub fn is_degraded(&self) -> bool {
+        !self.downgrades.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "running with reduced features:")?;
+        for d in &self.downgrades {
+            writeln!(f, "    {}", d)?;
+        }
+        if let Some(major) = self.actual.es {
+            write!(f, "    got GLES {}, ", major)?;
+        } else {
+            write!(f, "    got ")?;
+        }
+        write!(f, "{} samples, {}sRGB, {}-bit depth, {}-bit stencil",
+               self.actual.samples, if self.actual.srgb { "" } else { "no " },
+               self.actual.depth, self.actual.stencil)
+    }
+}
+
+// builds the window and its context, walking down the ladder until both
+// succeed. panics only when even the bottom rung fails.
+pub fn create(video: &VideoSubsystem, builder: &WindowBuilder, requested: Config)
+              -> (Window, GLContext, Report) {
+    let mut config = requested;
+    let mut downgrades = Vec::new();
+    loop {
+        config.apply(video);
+        let err = match builder.build() {
+            Ok(window) => match window.gl_create_context() {
+                Ok(ctx)  => {
+                    let actual = Config::current(video);
+                    debug!("context created with {:?}", actual);
+                    // the driver may also quietly hand out less than asked
+                    if actual.samples < config.samples {
+                        downgrades.push(format!("asked for {}x MSAA, got {}", config.samples, actual.samples));
+                    }
+                    if config.srgb && !actual.srgb {
+                        downgrades.push("asked for an sRGB framebuffer, didn't get one".to_string());
+                    }
+                    if actual.depth < config.depth {
+                        downgrades.push(format!("asked for {}-bit depth, got {}", config.depth, actual.depth));
+                    }
+                    if actual.stencil < config.stencil {
+                        downgrades.push(format!("asked for {}-bit stencil, got {}", config.stencil, actual.stencil));
+                    }
+                    return (window, ctx, Report { actual, downgrades });
+                },
+                Err(err) => err,
+            },
+            Err(err) => err.to_string(),
+        };
+        match config.degrade() {
+            Some((next, what)) => {
+                debug!("context creation with {:?} failed: {}", config, err);
+                downgrades.push(format!("{} ({})", what, err));
+                config = next;
+            },
+            None => panic!("failed to create GL context: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ladder(mut config: Config) -> Vec<String> {
+        let mut steps = Vec::new();
+        while let Some((next, what)) = config.degrade() {
+            steps.push(what);
+            config = next;
+        }
+        steps
+    }
+
+    #[test]
+    fn degrade_order() {
+        let full = Config { es: Some(3), samples: 8, srgb: true, depth: 24, stencil: 8 };
+        assert_eq!(ladder(full), vec![
+            "GLES 3 context unavailable, trying GLES 2",
+            "8x MSAA unavailable, trying 4",
+            "4x MSAA unavailable, trying 2",
+            "2x MSAA unavailable, trying 0",
+            "sRGB framebuffer unavailable",
+            "stencil buffer unavailable",
+            "24-bit depth buffer unavailable, trying 16",
+        ]);
+    }
+
+    #[test]
+    fn degrade_bottom() {
+        let bottom = Config { es: Some(2), samples: 0, srgb: false, depth: 16, stencil: 0 };
+        assert!(bottom.degrade().is_none());
+        let desktop = Config { es: None, ..bottom };
+        assert!(desktop.degrade().is_none());
+    }
+
+    #[test]
+    fn degrade_skips_what_was_not_asked() {
+        let config = Config { es: None, samples: 3, srgb: false, depth: 24, stencil: 0 };
+        assert_eq!(ladder(config), vec![
+            "3x MSAA unavailable, trying 1",
+            "1x MSAA unavailable, trying 0",
+            "24-bit depth buffer unavailable, trying 16",
+        ]);
+    }
+}
@@ -0,0 +1,159 @@
+use gleam::gl;
+use gleam::gl::types::{GLfloat, GLsizei};
+
+use GlPtr;
+use load_program;
+use buffer::StreamBuffer;
+use caps::Capabilities;
+use collision::Aabb;
+use geom::Vec3;
+use label::{self, Object};
+use pipeline::PipelineState;
+use program::Program;
+use state::StateCache;
+use vertex::{VertexLayout, Packing, Format};
+use vfs::Vfs;
+
+pub type Color = [GLfloat; 4];
+
+pub const RED: Color   = [1.0, 0.0, 0.0, 1.0];
+pub const GREEN: Color = [0.0, 1.0, 0.0, 1.0];
+pub const BLUE: Color  = [0.0, 0.0, 1.0, 1.0];
+
+pub const IDENTITY: [GLfloat; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+// position + color
+const VERTEX_SIZE: usize = 7;
+
+// per primitive type, per frame. anything past this is dropped.
+const MAX_VERTICES: usize = 16384;
+
+// immediate-mode lines and points for debugging. calls accumulate during the
+// frame and go out in one draw per primitive type on flush(), with a plain
+// unlit shader. coordinates are in clip space.
+pub struct DebugDraw {
+    gl: GlPtr,
+    program: Program,
+    layout: VertexLayout,
+    stream: StreamBuffer,
+    // desktop GL ignores gl_PointSize unless asked
+    point_size_enable: bool,
+    lines: Vec<GLfloat>,
+    points: Vec<GLfloat>,
+}
+
+impl DebugDraw {
+    pub fn new(gl: &GlPtr, vfs: &dyn Vfs, caps: &Capabilities) -> DebugDraw {
+        let program = load_program(gl, vfs, caps, "debug.v.glsl", "debug.f.glsl");
+        label::object(Object::Program, program, "debug draw");
+
+        // colors don't need more than 8 bits a channel
+        let layout = VertexLayout::new(Packing::Interleaved, caps)
+            .attribute(gl.get_attrib_location(program, "position"), 3)
+            .attribute_as(gl.get_attrib_location(program, "color"), 4, Format::NormalizedUnsignedByte);
+
+        // room for both primitive types in one frame
+        let segment = 2 * MAX_VERTICES * layout.stride();
+
+        DebugDraw {
+            gl: gl.clone(),
+            program: Program::reflect(gl, program),
+            layout,
+            stream: StreamBuffer::new(gl, caps, gl::ARRAY_BUFFER, segment).labeled("debug draw vertices"),
+            point_size_enable: caps.point_size_needs_enable(),
+            lines: Vec::new(),
+            points: Vec::new(),
+        }
+    }
+
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: Color) {
+        if self.lines.len() + 2 * VERTEX_SIZE <= MAX_VERTICES * VERTEX_SIZE {
+            self.lines.extend_from_slice(&a);
+            self.lines.extend_from_slice(&color);
+            self.lines.extend_from_slice(&b);
+            self.lines.extend_from_slice(&color);
+        }
+    }
+
+    pub fn point(&mut self, p: Vec3, color: Color) {
+        if self.points.len() + VERTEX_SIZE <= MAX_VERTICES * VERTEX_SIZE {
+            self.points.extend_from_slice(&p);
+            self.points.extend_from_slice(&color);
+        }
+    }
+
+    // the twelve edges of the box
+    pub fn aabb(&mut self, b: &Aabb, color: Color) {
+        let corner = |i: usize| [
+            if i & 1 == 0 { b.min[0] } else { b.max[0] },
+            if i & 2 == 0 { b.min[1] } else { b.max[1] },
+            if i & 4 == 0 { b.min[2] } else { b.max[2] },
+        ];
+        // corners one bit apart share an edge
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    // unit x, y and z axes of a column-major transform, in red, green and
+    // blue, from its origin
+    pub fn axis(&mut self, m: &[GLfloat; 16]) {
+        let origin = [m[12], m[13], m[14]];
+        for (i, &color) in [RED, GREEN, BLUE].iter().enumerate() {
+            let tip = [m[12] + m[i*4], m[13] + m[i*4+1], m[14] + m[i*4+2]];
+            self.line(origin, tip, color);
+        }
+    }
+
+    // a square grid of the given size centred on the origin, in the z=0
+    // plane, with a line every size/divisions
+    pub fn grid(&mut self, size: GLfloat, divisions: usize, color: Color) {
+        let half = size * 0.5;
+        for i in 0..=divisions {
+            let t = -half + size * i as GLfloat / divisions as GLfloat;
+            self.line([t, -half, 0.0], [t, half, 0.0], color);
+            self.line([-half, t, 0.0], [half, t, 0.0], color);
+        }
+    }
+
+    pub fn flush(&mut self, state: &mut StateCache) {
+        if self.lines.is_empty() && self.points.is_empty() {
+            return;
+        }
+
+        state.set_pipeline(&PipelineState::default());
+        self.program.set(state, "transform", &IDENTITY);
+        if self.point_size_enable {
+            state.enable(gl::VERTEX_PROGRAM_POINT_SIZE);
+        }
+
+        for &(mode, vertices) in &[(gl::LINES, &self.lines), (gl::POINTS, &self.points)] {
+            if vertices.is_empty() {
+                continue;
+            }
+            let packed = self.layout.pack(vertices);
+            let offset = self.stream.push(state, &packed[0]);
+            self.layout.bind_at(state, self.stream.buffer, offset);
+            state.draw_arrays(mode, 0, (vertices.len() / VERTEX_SIZE) as GLsizei);
+        }
+
+        self.stream.finish_frame(state);
+        self.lines.clear();
+        self.points.clear();
+    }
+}
+
+impl Drop for DebugDraw {
+    fn drop(&mut self) {
+        self.gl.delete_program(self.program.id);
+    }
+}
@@ -0,0 +1,66 @@
+use sdl2::VideoSubsystem;
+use std::env;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GpuPreference {
+    // whatever the system picks, usually the integrated GPU
+    Default,
+    Discrete,
+    Integrated,
+}
+
+impl GpuPreference {
+    // hybrid laptops pick the GPU when the GL library is loaded, so this
+    // must run before SDL's video subsystem comes up. only Mesa's PRIME and
+    // NVIDIA's render offload listen to these; elsewhere it's the driver
+    // control panel's call.
+    pub fn apply(self) {
+        match self {
+            GpuPreference::Default    => {},
+            GpuPreference::Discrete   => {
+                env::set_var("DRI_PRIME", "1");
+                env::set_var("__NV_PRIME_RENDER_OFFLOAD", "1");
+                env::set_var("__GLX_VENDOR_LIBRARY_NAME", "nvidia");
+            },
+            GpuPreference::Integrated => {
+                env::set_var("DRI_PRIME", "0");
+                env::remove_var("__NV_PRIME_RENDER_OFFLOAD");
+            },
+        }
+    }
+}
+
+// top-left position that centres a window of the given size on a display
+pub fn centered_on(video: &VideoSubsystem, display: i32, width: u32, height: u32) -> (i32, i32) {
+    let count = video.num_video_displays().unwrap_or(1);
+    if display < 0 || display >= count {
+        panic!("no display {}; there are {}", display, count);
+    }
+    let bounds = match video.display_bounds(display) {
+        Ok(b)    => b,
+        Err(err) => panic!("failed to query display {}: {}", display, err),
+    };
+    (bounds.x() + (bounds.width() as i32 - width as i32) / 2,
+     bounds.y() + (bounds.height() as i32 - height as i32) / 2)
+}
+
+// one line per display: name, bounds, mode and DPI where SDL knows them
+pub fn describe(video: &VideoSubsystem) -> Vec<String> {
+    let count = video.num_video_displays().unwrap_or(0);
+    (0..count).map(|i| {
+        let name = video.display_name(i).unwrap_or_else(|_| "unknown".to_string());
+        let bounds = match video.display_bounds(i) {
+            Ok(b)  => format!("{}x{} at {},{}", b.width(), b.height(), b.x(), b.y()),
+            Err(_) => "unknown bounds".to_string(),
+        };
+        let refresh = match video.current_display_mode(i) {
+            Ok(m)  => format!(", {} Hz", m.refresh_rate),
+            Err(_) => String::new(),
+        };
+        let dpi = match video.display_dpi(i) {
+            Ok((ddpi, _, _)) => format!(", {:.0} dpi", ddpi),
+            Err(_)           => String::new(),
+        };
+        format!("display {}: {}, {}{}{}", i, name, bounds, refresh, dpi)
+    }).collect()
+}
@@ -0,0 +1,36 @@
+use gleam::gl::types::{GLenum, GLsizei};
+
+use buffer::Buffer;
+use command::{CommandBuffer, RenderCommand};
+use material::Material;
+use pipeline::PipelineState;
+use vertex::VertexLayout;
+
+// vertex buffers in a layout and the indices to draw them with
+pub struct Geometry {
+    pub layout: VertexLayout,
+    pub vertex_buffers: Vec<Buffer>,
+    pub index_buffer: Buffer,
+    pub mode: GLenum,
+    pub count: GLsizei,
+    pub index_type: GLenum,
+}
+
+// one thing to draw: some geometry, the material to shade it with and the
+// fixed-function state to draw it with
+pub struct DrawItem<'a> {
+    pub geometry: &'a Geometry,
+    pub material: &'a Material,
+    pub pipeline: PipelineState,
+}
+
+impl<'a> DrawItem<'a> {
+    pub fn record(&self, cmds: &mut CommandBuffer<'a>) {
+        let g = self.geometry;
+        cmds.push(RenderCommand::SetPipeline(self.pipeline));
+        self.material.record(cmds);
+        cmds.push(RenderCommand::BindVertexBuffers { layout: &g.layout, buffers: &g.vertex_buffers });
+        cmds.push(RenderCommand::BindIndexBuffer(&g.index_buffer));
+        cmds.push(RenderCommand::DrawElements { mode: g.mode, count: g.count, typ: g.index_type });
+    }
+}
@@ -0,0 +1,145 @@
+use gleam::gl;
+use gleam::gl::types::{GLuint, GLint, GLenum, GLsizei, GLsizeiptr, GLintptr, GLboolean, GLbitfield, GLclampf, GLclampd};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::os::raw::c_void;
+use std::ptr;
+
+use GlPtr;
+use caps::Capabilities;
+
+// a GL for tests that writes down the calls made to it instead of drawing
+// anything, so code that talks to GL can be checked for what it says.
+// only the entry points some test reaches are there; gleam panics on any
+// other. calls are kept per thread, so tests running side by side don't
+// see each other's.
+
+thread_local! {
+    static CALLS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static NEXT_NAME: Cell<GLuint> = const { Cell::new(1) };
+}
+
+fn record(call: String) {
+    CALLS.with(|c| c.borrow_mut().push(call));
+}
+
+// the calls made on this thread since the last take(), oldest first
+pub fn take() -> Vec<String> {
+    CALLS.with(|c| c.borrow_mut().drain(..).collect())
+}
+
+extern "system" fn gen_buffers(n: GLsizei, names: *mut GLuint) {
+    for i in 0..n as usize {
+        let name = NEXT_NAME.with(|next| next.replace(next.get() + 1));
+        unsafe { *names.add(i) = name };
+    }
+    record(format!("glGenBuffers({})", n));
+}
+
+extern "system" fn delete_buffers(n: GLsizei, names: *const GLuint) {
+    let names = unsafe { std::slice::from_raw_parts(names, n as usize) };
+    record(format!("glDeleteBuffers({:?})", names));
+}
+
+extern "system" fn bind_buffer(target: GLenum, buffer: GLuint) {
+    record(format!("glBindBuffer(0x{:x}, {})", target, buffer));
+}
+
+extern "system" fn buffer_data(target: GLenum, size: GLsizeiptr, _data: *const c_void, usage: GLenum) {
+    record(format!("glBufferData(0x{:x}, {}, 0x{:x})", target, size, usage));
+}
+
+extern "system" fn buffer_sub_data(target: GLenum, offset: GLintptr, size: GLsizeiptr, _data: *const c_void) {
+    record(format!("glBufferSubData(0x{:x}, {}, {})", target, offset, size));
+}
+
+extern "system" fn enable(cap: GLenum) {
+    record(format!("glEnable(0x{:x})", cap));
+}
+
+extern "system" fn disable(cap: GLenum) {
+    record(format!("glDisable(0x{:x})", cap));
+}
+
+extern "system" fn depth_func(func: GLenum) {
+    record(format!("glDepthFunc(0x{:x})", func));
+}
+
+extern "system" fn depth_mask(flag: GLboolean) {
+    record(format!("glDepthMask({})", flag));
+}
+
+extern "system" fn cull_face(mode: GLenum) {
+    record(format!("glCullFace(0x{:x})", mode));
+}
+
+extern "system" fn blend_func(src: GLenum, dst: GLenum) {
+    record(format!("glBlendFunc(0x{:x}, 0x{:x})", src, dst));
+}
+
+extern "system" fn stencil_func(func: GLenum, reference: GLint, mask: GLuint) {
+    record(format!("glStencilFunc(0x{:x}, {}, 0x{:x})", func, reference, mask));
+}
+
+extern "system" fn stencil_op(fail: GLenum, depth_fail: GLenum, pass: GLenum) {
+    record(format!("glStencilOp(0x{:x}, 0x{:x}, 0x{:x})", fail, depth_fail, pass));
+}
+
+extern "system" fn bind_framebuffer(target: GLenum, framebuffer: GLuint) {
+    record(format!("glBindFramebuffer(0x{:x}, {})", target, framebuffer));
+}
+
+extern "system" fn viewport(x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+    record(format!("glViewport({}, {}, {}, {})", x, y, width, height));
+}
+
+extern "system" fn clear_color(r: GLclampf, g: GLclampf, b: GLclampf, a: GLclampf) {
+    record(format!("glClearColor({}, {}, {}, {})", r, g, b, a));
+}
+
+extern "system" fn clear_depth(depth: GLclampd) {
+    record(format!("glClearDepth({})", depth));
+}
+
+extern "system" fn clear(mask: GLbitfield) {
+    record(format!("glClear(0x{:x})", mask));
+}
+
+pub fn load() -> GlPtr {
+    unsafe {
+        gl::GlFns::load_with(|name| match name {
+            "glGenBuffers"      => gen_buffers as *const c_void,
+            "glDeleteBuffers"   => delete_buffers as *const c_void,
+            "glBindBuffer"      => bind_buffer as *const c_void,
+            "glBufferData"      => buffer_data as *const c_void,
+            "glBufferSubData"   => buffer_sub_data as *const c_void,
+            "glEnable"          => enable as *const c_void,
+            "glDisable"         => disable as *const c_void,
+            "glDepthFunc"       => depth_func as *const c_void,
+            "glDepthMask"       => depth_mask as *const c_void,
+            "glCullFace"        => cull_face as *const c_void,
+            "glBlendFunc"       => blend_func as *const c_void,
+            "glStencilFunc"     => stencil_func as *const c_void,
+            "glStencilOp"       => stencil_op as *const c_void,
+            "glBindFramebuffer" => bind_framebuffer as *const c_void,
+            "glViewport"        => viewport as *const c_void,
+            "glClearColor"      => clear_color as *const c_void,
+            "glClearDepth"      => clear_depth as *const c_void,
+            "glClear"           => clear as *const c_void,
+            _                   => ptr::null(),
+        })
+    }
+}
+
+// a bare GL 2.1 context: no extensions, so nothing optional gets looked up
+pub fn caps() -> Capabilities {
+    Capabilities {
+        es: false,
+        version: (2, 1),
+        glsl_version: (1, 2),
+        extensions: HashSet::new(),
+        max_texture_size: 2048,
+        max_texture_units: 8,
+        max_vertex_attribs: 16,
+    }
+}
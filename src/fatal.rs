@@ -0,0 +1,53 @@
+use sdl2::messagebox::{show_simple_message_box, MESSAGEBOX_ERROR};
+use sdl2::sys;
+use std::any::Any;
+use std::panic;
+use std::process;
+use std::ptr;
+use std::thread;
+
+// every fatal error in the program is a panic with a readable message (shader
+// info logs included), which goes to a console nobody sees when started
+// from a desktop icon. this hook keeps the usual stderr output and also puts
+// the message in a message box before the process goes down.
+//
+// on the main thread it then tears down the GL context and SDL itself, which
+// puts back the original video mode (and on KMS, the CRTC) so a fullscreen
+// crash doesn't leave the display unusable, and aborts. unwinding from there
+// would only drop handles to things that no longer exist.
+pub fn install_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default(info);
+        if thread::current().name() != Some("main") {
+            return;
+        }
+        restore_display();
+        let msg = message(info.payload());
+        // no display, or SDL not usable; stderr will have to do
+        let _ = show_simple_message_box(MESSAGEBOX_ERROR, "Hello GL! failed", &msg, None);
+        process::abort();
+    }));
+}
+
+fn restore_display() {
+    unsafe {
+        let ctx = sys::SDL_GL_GetCurrentContext();
+        if !ctx.is_null() {
+            sys::SDL_GL_MakeCurrent(sys::SDL_GL_GetCurrentWindow(), ptr::null_mut());
+            sys::SDL_GL_DeleteContext(ctx);
+        }
+        // destroys the windows and restores the desktop mode
+        sys::SDL_Quit();
+    }
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown error".to_string()
+    }
+}
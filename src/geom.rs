@@ -0,0 +1,297 @@
+use gleam::gl::types::GLfloat;
+
+use collision::Ray;
+
+// the queries the demo doesn't use yet (planes, closest points) are only
+// called from the tests; allow(dead_code) is on each of those alone
+
+pub type Vec3 = [GLfloat; 3];
+
+// below this, a ray is treated as parallel to a triangle or plane
+const EPSILON: GLfloat = 1e-7;
+
+pub fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale(a: Vec3, s: GLfloat) -> Vec3 {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub fn dot(a: Vec3, b: Vec3) -> GLfloat {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn length(a: Vec3) -> GLfloat {
+    dot(a, a).sqrt()
+}
+
+pub fn normalize(a: Vec3) -> Vec3 {
+    let l = length(a);
+    if l > 0.0 { scale(a, 1.0 / l) } else { a }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    // unit normal, and signed distance of the plane from the origin along it
+    pub normal: Vec3,
+    pub d: GLfloat,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Plane {
+    pub fn from_point_normal(point: Vec3, normal: Vec3) -> Plane {
+        let n = normalize(normal);
+        Plane { normal: n, d: dot(n, point) }
+    }
+
+    pub fn from_triangle(a: Vec3, b: Vec3, c: Vec3) -> Plane {
+        Plane::from_point_normal(a, cross(sub(b, a), sub(c, a)))
+    }
+
+    // positive on the side the normal points to
+    pub fn distance(&self, p: Vec3) -> GLfloat {
+        dot(self.normal, p) - self.d
+    }
+
+    pub fn closest_point(&self, p: Vec3) -> Vec3 {
+        sub(p, scale(self.normal, self.distance(p)))
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleHit {
+    pub t: GLfloat,
+    // barycentric weights of the second and third vertices
+    pub u: GLfloat,
+    pub v: GLfloat,
+}
+
+// Möller & Trumbore, "Fast, Minimum Storage Ray/Triangle Intersection"
+// (1997). hits from behind count too; check the sign of the determinant
+// first if back faces should be skipped.
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn ray_triangle(ray: &Ray, a: Vec3, b: Vec3, c: Vec3) -> Option<TriangleHit> {
+    let e1 = sub(b, a);
+    let e2 = sub(c, a);
+    let p = cross(ray.direction, e2);
+    let det = dot(e1, p);
+    if det.abs() < EPSILON {
+        return None;
+    }
+    let inv = 1.0 / det;
+
+    let s = sub(ray.origin, a);
+    let u = dot(s, p) * inv;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = cross(s, e1);
+    let v = dot(ray.direction, q) * inv;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = dot(e2, q) * inv;
+    if t < 0.0 {
+        return None;
+    }
+    Some(TriangleHit { t, u, v })
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn ray_plane(ray: &Ray, plane: &Plane) -> Option<GLfloat> {
+    let denom = dot(plane.normal, ray.direction);
+    if denom.abs() < EPSILON {
+        return None;
+    }
+    let t = (plane.d - dot(plane.normal, ray.origin)) / denom;
+    if t < 0.0 { None } else { Some(t) }
+}
+
+// parameter along the ray of the point nearest p, clamped to the ray start
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn closest_on_ray(ray: &Ray, p: Vec3) -> GLfloat {
+    let dd = dot(ray.direction, ray.direction);
+    if dd == 0.0 {
+        return 0.0;
+    }
+    (dot(sub(p, ray.origin), ray.direction) / dd).max(0.0)
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn closest_on_segment(a: Vec3, b: Vec3, p: Vec3) -> Vec3 {
+    let ab = sub(b, a);
+    let dd = dot(ab, ab);
+    if dd == 0.0 {
+        return a;
+    }
+    let t = (dot(sub(p, a), ab) / dd).clamp(0.0, 1.0);
+    add(a, scale(ab, t))
+}
+
+// closest point on triangle abc to p, from Ericson's "Real-Time Collision
+// Detection" 5.1.5: find the Voronoi region of p, then project onto it
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn closest_on_triangle(a: Vec3, b: Vec3, c: Vec3, p: Vec3) -> Vec3 {
+    let ab = sub(b, a);
+    let ac = sub(c, a);
+    let ap = sub(p, a);
+    let d1 = dot(ab, ap);
+    let d2 = dot(ac, ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = sub(p, b);
+    let d3 = dot(ab, bp);
+    let d4 = dot(ac, bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return add(a, scale(ab, d1 / (d1 - d3)));
+    }
+
+    let cp = sub(p, c);
+    let d5 = dot(ab, cp);
+    let d6 = dot(ac, cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return add(a, scale(ac, d2 / (d2 - d6)));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return add(b, scale(sub(c, b), (d4 - d3) / ((d4 - d3) + (d5 - d6))));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    add(a, add(scale(ab, vb * denom), scale(ac, vc * denom)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn near(a: Vec3, b: Vec3) -> bool {
+        length(sub(a, b)) < 1e-5
+    }
+
+    const A: Vec3 = [0.0, 0.0, 0.0];
+    const B: Vec3 = [1.0, 0.0, 0.0];
+    const C: Vec3 = [0.0, 1.0, 0.0];
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray {
+        Ray { origin, direction }
+    }
+
+    #[test]
+    fn ray_triangle_hit() {
+        let hit = ray_triangle(&ray([0.25, 0.5, -2.0], [0.0, 0.0, 1.0]), A, B, C).unwrap();
+        assert_eq!(hit, TriangleHit { t: 2.0, u: 0.25, v: 0.5 });
+        // distances are in units of the direction's length
+        let hit = ray_triangle(&ray([0.25, 0.5, -2.0], [0.0, 0.0, 4.0]), A, B, C).unwrap();
+        assert_eq!(hit.t, 0.5);
+        // from the back too
+        let hit = ray_triangle(&ray([0.25, 0.5, 2.0], [0.0, 0.0, -1.0]), A, B, C).unwrap();
+        assert_eq!(hit.t, 2.0);
+    }
+
+    #[test]
+    fn ray_triangle_miss() {
+        assert_eq!(ray_triangle(&ray([1.0, 1.0, -1.0], [0.0, 0.0, 1.0]), A, B, C), None);
+        assert_eq!(ray_triangle(&ray([-0.1, 0.5, -1.0], [0.0, 0.0, 1.0]), A, B, C), None);
+        assert_eq!(ray_triangle(&ray([0.5, -0.1, -1.0], [0.0, 0.0, 1.0]), A, B, C), None);
+    }
+
+    #[test]
+    fn ray_triangle_parallel() {
+        // in the triangle's plane, and above it
+        assert_eq!(ray_triangle(&ray([-1.0, 0.25, 0.0], [1.0, 0.0, 0.0]), A, B, C), None);
+        assert_eq!(ray_triangle(&ray([-1.0, 0.25, 1.0], [1.0, 0.0, 0.0]), A, B, C), None);
+    }
+
+    #[test]
+    fn ray_triangle_behind() {
+        assert_eq!(ray_triangle(&ray([0.25, 0.25, 1.0], [0.0, 0.0, 1.0]), A, B, C), None);
+    }
+
+    #[test]
+    fn planes() {
+        let plane = Plane::from_point_normal([0.0, 0.0, 2.0], [0.0, 0.0, 5.0]);
+        assert_eq!(plane, Plane { normal: [0.0, 0.0, 1.0], d: 2.0 });
+        assert_eq!(plane.distance([3.0, 1.0, 5.0]), 3.0);
+        assert_eq!(plane.distance([3.0, 1.0, 0.0]), -2.0);
+        assert!(near(plane.closest_point([3.0, 1.0, 5.0]), [3.0, 1.0, 2.0]));
+
+        // counter-clockwise seen from +z
+        let plane = Plane::from_triangle(A, B, C);
+        assert_eq!(plane, Plane { normal: [0.0, 0.0, 1.0], d: 0.0 });
+    }
+
+    #[test]
+    fn ray_plane_hits() {
+        let plane = Plane::from_point_normal([0.0, 0.0, 2.0], [0.0, 0.0, 1.0]);
+        assert_eq!(ray_plane(&ray([1.0, 1.0, 0.0], [0.0, 0.0, 1.0]), &plane), Some(2.0));
+        // from the far side
+        assert_eq!(ray_plane(&ray([1.0, 1.0, 4.0], [0.0, 0.0, -2.0]), &plane), Some(1.0));
+        // parallel
+        assert_eq!(ray_plane(&ray([1.0, 1.0, 0.0], [1.0, 0.0, 0.0]), &plane), None);
+        // behind
+        assert_eq!(ray_plane(&ray([1.0, 1.0, 0.0], [0.0, 0.0, -1.0]), &plane), None);
+    }
+
+    #[test]
+    fn closest_on_lines() {
+        let r = ray([0.0, 0.0, 0.0], [2.0, 0.0, 0.0]);
+        assert_eq!(closest_on_ray(&r, [4.0, 3.0, 0.0]), 2.0);
+        assert_eq!(closest_on_ray(&r, [-4.0, 3.0, 0.0]), 0.0);
+
+        assert_eq!(closest_on_segment(A, B, [0.5, 3.0, 0.0]), [0.5, 0.0, 0.0]);
+        assert_eq!(closest_on_segment(A, B, [-2.0, 3.0, 0.0]), A);
+        assert_eq!(closest_on_segment(A, B, [3.0, 3.0, 0.0]), B);
+        assert_eq!(closest_on_segment(A, A, [3.0, 3.0, 0.0]), A);
+    }
+
+    #[test]
+    fn closest_on_triangle_regions() {
+        let cases = [
+            // vertex regions
+            ([-1.0, -1.0, 0.0], A),
+            ([2.0, -0.5, 0.0], B),
+            ([-0.5, 2.0, 0.0], C),
+            // edge regions
+            ([0.5, -1.0, 0.0], [0.5, 0.0, 0.0]),
+            ([-1.0, 0.5, 0.0], [0.0, 0.5, 0.0]),
+            ([1.0, 1.0, 0.0], [0.5, 0.5, 0.0]),
+            // inside, off the plane
+            ([0.25, 0.25, 3.0], [0.25, 0.25, 0.0]),
+        ];
+        for &(p, expected) in cases.iter() {
+            let got = closest_on_triangle(A, B, C, p);
+            assert!(near(got, expected), "closest to {:?} is {:?}, not {:?}", p, expected, got);
+        }
+    }
+}
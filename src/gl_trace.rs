@@ -0,0 +1,112 @@
+use gleam::gl;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::rc::Rc;
+use std::time::Duration;
+
+use GlPtr;
+
+// `--trace-gl frame=N`: writes the GL calls made during frame N of the main
+// loop (counting from 0) to gl-trace-frame-N.txt, one name per line in
+// order, so the traces of a working and a broken setup can be diffed.
+// `frame=N,timing` adds how long each call took, which differs from run to
+// run and so gets in the way of diffing.
+//
+// the wrapper is gleam's ProfilingGl, which hands over each call's name and
+// duration but not its arguments. it only reports calls that took longer
+// than its threshold. with a threshold of zero that's every call where
+// Instant counts in nanoseconds (Linux, macOS), since no call into the
+// driver is free; where it counts in coarser steps (Windows) a call too
+// quick to measure is left out of the trace.
+pub struct GlTrace {
+    target: u64,
+    timing: bool,
+    frame: Cell<u64>,
+    out: RefCell<Option<BufWriter<File>>>,
+}
+
+// what --trace-gl asked for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub frame: u64,
+    pub timing: bool,
+}
+
+// takes "frame=N" or "frame=N,timing"
+pub fn parse(arg: &str) -> Option<Request> {
+    let (frame, timing) = match arg.split_once(',') {
+        Some((frame, "timing")) => (frame, true),
+        Some(_)                 => return None,
+        None                    => (arg, false),
+    };
+    let frame = frame.strip_prefix("frame=")?.parse().ok()?;
+    Some(Request { frame, timing })
+}
+
+impl GlTrace {
+    // wraps gl so its calls can be traced; the context has to be made with
+    // the returned one
+    pub fn wrap(gl: GlPtr, request: Request) -> (GlPtr, Rc<GlTrace>) {
+        let trace = Rc::new(GlTrace {
+            target: request.frame,
+            timing: request.timing,
+            // not in a frame until the main loop starts
+            frame: Cell::new(u64::MAX),
+            out: RefCell::new(None),
+        });
+        let t = trace.clone();
+        let gl = gl::ProfilingGl::wrap(gl, Duration::from_nanos(0), move |name, duration| t.call(name, duration));
+        (gl, trace)
+    }
+
+    fn call(&self, name: &str, duration: Duration) {
+        if let Some(ref mut out) = *self.out.borrow_mut() {
+            // the trace is only for debugging; a failed write isn't worth
+            // stopping the frame for
+            let _ = if self.timing {
+                writeln!(out, "{} {}ns", name, duration.as_nanos())
+            } else {
+                writeln!(out, "{}", name)
+            };
+        }
+    }
+
+    // called at the start of each main loop iteration
+    pub fn next_frame(&self) {
+        let frame = self.frame.get().wrapping_add(1);
+        self.frame.set(frame);
+
+        let mut out = self.out.borrow_mut();
+        if let Some(mut f) = out.take() {
+            match f.flush() {
+                Ok(())   => info!("GL calls of frame {} written to {}", self.target, file_name(self.target)),
+                Err(err) => warn!("couldn't write {}: {}", file_name(self.target), err),
+            }
+        }
+        if frame == self.target {
+            match File::create(file_name(frame)) {
+                Ok(f)    => *out = Some(BufWriter::new(f)),
+                Err(err) => warn!("couldn't create {}: {}", file_name(frame), err),
+            }
+        }
+    }
+}
+
+fn file_name(frame: u64) -> String {
+    format!("gl-trace-frame-{}.txt", frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_requests() {
+        assert_eq!(parse("frame=3"), Some(Request { frame: 3, timing: false }));
+        assert_eq!(parse("frame=0,timing"), Some(Request { frame: 0, timing: true }));
+        assert_eq!(parse("frame=3,names"), None);
+        assert_eq!(parse("frame="), None);
+        assert_eq!(parse("3"), None);
+    }
+}
@@ -1,60 +1,112 @@
 extern crate sdl2;
 extern crate gleam;
+extern crate notify;
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+#[macro_use(info_span, trace_span)]
+extern crate tracing;
+extern crate tracing_chrome;
+extern crate tracing_subscriber;
+extern crate toml;
+extern crate rhai;
+extern crate libloading;
+
+mod assets;
+mod background;
+mod bake;
+mod buffer;
+mod caps;
+mod clock;
+mod collision;
+mod command;
+mod context;
+mod debug_draw;
+mod display;
+mod draw;
+#[cfg(test)]
+mod fake_gl;
+mod fatal;
+mod geom;
+mod gl_trace;
+mod info;
+mod input;
+mod label;
+mod loader;
+mod material;
+mod memory;
+mod mesh;
+mod pass;
+mod pipeline;
+mod plugin;
+mod profile;
+mod program;
+mod renderdoc;
+mod sampler;
+mod script;
+mod shader;
+mod state;
+mod upload;
+mod vertex;
+mod vfs;
 
-use sdl2::surface::Surface;
-use sdl2::pixels::PixelFormatEnum;
-use sdl2::keyboard::Keycode;
 use gleam::gl;
-use gleam::gl::types::{GLuint, GLint, GLfloat, GLenum, GLsizei, GLushort};
-use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::Path;
+use gleam::gl::types::{GLuint, GLint, GLfloat, GLenum, GLsizei};
 use std::mem;
-
-struct Uniforms {
-    fade_factor: GLint,
-    textures: [GLint; 2],
-}
-
-struct Attributes {
-    position: GLint,
-}
+use std::env;
+use std::sync::Arc;
+use background::{Background, Backdrop};
+use buffer::{Buffer, Usage};
+use caps::Capabilities;
+use clock::{FixedStep, Interpolated};
+use command::CommandBuffer;
+use context::Config;
+use collision::{Aabb, Ray};
+use debug_draw::DebugDraw;
+use display::GpuPreference;
+use draw::{DrawItem, Geometry};
+use mesh::{Mesh, Tolerance};
+use gl_trace::GlTrace;
+use input::{Action, Bindings};
+use label::Object;
+use loader::{Image, Loader};
+use material::Material;
+use pass::RenderPass;
+use pipeline::PipelineState;
+use sampler::Sampler;
+use plugin::Plugins;
+use program::Program;
+use renderdoc::RenderDoc;
+use upload::Uploader;
+use script::Script;
+use shader::Dialect;
+use state::StateCache;
+use vertex::{VertexLayout, Packing, Format};
+use vfs::Vfs;
 
 struct Resources {
-    vertex_buffer: GLuint,
-    element_buffer: GLuint,
+    gl: GlPtr,
+    // kept for picking
+    mesh: Mesh,
+    quad: Geometry,
     textures: [GLuint; 2],
-    program: GLuint,
-    uniforms: Uniforms,
-    attributes: Attributes,
-    fade_factor: GLfloat,
+    material: Material,
 }
 
-type GlPtr = std::rc::Rc<gl::Gl>;
-
-fn make_buffer<T>(gl: &GlPtr, target: GLenum, data: &[T]) -> GLuint {
-    let buffers = gl.gen_buffers(1);
-    let buffer = match buffers.len() {
-        0 => panic!("couldn't create buffer"),
-        _ => buffers[0],
-    };
-    gl.bind_buffer(target, buffer);
-    gl.buffer_data_untyped(target, mem::size_of_val(data) as isize, data.as_ptr() as *const _, gl::STATIC_DRAW);
-    buffer
+// the buffers clean up after themselves
+impl Drop for Resources {
+    fn drop(&mut self) {
+        self.gl.delete_textures(&self.textures);
+        for &t in self.textures.iter() {
+            memory::texture_deleted(t);
+        }
+        self.gl.delete_program(self.material.program.id);
+    }
 }
 
-fn make_texture(gl: &GlPtr, filename: &str) -> GLuint {
-    let path = Path::new(filename);
-    let bmp = match Surface::load_bmp(&path) {
-        Ok(s) => s,
-        Err(err) => panic!("couldn't load {}: {}", filename, err),
-    };
-    let fmt = Surface::new(1,1,PixelFormatEnum::RGB24).unwrap().pixel_format();
-    let rgb = match bmp.convert(&fmt) {
-        Ok(s) => s,
-        Err(err) => panic!("couldn't convert {} to RGB: {}", filename, err),
-    };
+type GlPtr = std::rc::Rc<gl::Gl>;
 
+fn make_texture(gl: &GlPtr, name: &str, image: &Image) -> GLuint {
     let textures = gl.gen_textures(1);
     let texture = match textures.len() {
         0 => panic!("couldn't create texture"),
@@ -66,136 +118,334 @@ fn make_texture(gl: &GlPtr, filename: &str) -> GLuint {
     gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S,     gl::CLAMP_TO_EDGE as GLint);
     gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T,     gl::CLAMP_TO_EDGE as GLint);
 
-    let width = rgb.width();
-    let height = rgb.height();
-    rgb.with_lock(|pixels| {
-        gl.tex_image_2d(
-            gl::TEXTURE_2D, 0,
-            gl::RGB as GLint,
-            width as GLsizei, height as GLsizei, 0,
-            gl::RGB, gl::UNSIGNED_BYTE,
-            Some(pixels.as_ref())
-        );
-    });
+    gl.tex_image_2d(
+        gl::TEXTURE_2D, 0,
+        gl::RGB as GLint,
+        image.width as GLsizei, image.height as GLsizei, 0,
+        gl::RGB, gl::UNSIGNED_BYTE,
+        Some(&image.pixels)
+    );
+    memory::texture_allocated(texture, memory::image_bytes(image.width as usize, image.height as usize, 3, false));
+    label::object(Object::Texture, texture, name);
 
     texture
 }
 
-fn make_shader(gl: &GlPtr, typ: GLenum, filename: &str) -> GLuint {
-    let path = Path::new(filename);
-    let file = match File::open(path) {
-        Ok(f)    => f,
-        Err(err) => panic!("couldn't open shader {}: {}", filename, err),
-    };
-    let mut r = BufReader::new(file);
-    let mut source: Vec<u8> = Vec::new();
-    match r.read_to_end(&mut source) {
-        Ok(_)    => (),
-        Err(err) => panic!("couldn't read shader {}: {}", filename, err),
-    };
-
+// the info log if it doesn't compile
+fn compile_shader(gl: &GlPtr, typ: GLenum, source: &str) -> Result<GLuint, String> {
     let shader = match gl.create_shader(typ) {
         0 => panic!("couldn't create shader object: {}", gl.get_error()),
         s => s,
     };
-    gl.shader_source(shader, &[source.as_slice()]);
+    gl.shader_source(shader, &[source.as_bytes()]);
     gl.compile_shader(shader);
 
     match gl.get_shader_iv(shader, gl::COMPILE_STATUS) {
-        0 => panic!("failed to compile {}: {}", filename, gl.get_shader_info_log(shader)),
-        _ => ()
+        0 => {
+            let log = gl.get_shader_info_log(shader);
+            gl.delete_shader(shader);
+            Err(log)
+        },
+        _ => Ok(shader),
+    }
+}
+
+fn make_shader(gl: &GlPtr, vfs: &dyn Vfs, caps: &Capabilities, typ: GLenum, filename: &str) -> Result<GLuint, String> {
+    let source = match vfs.read(filename) {
+        Ok(s)    => String::from_utf8_lossy(&s).into_owned(),
+        Err(err) => return Err(err.to_string()),
     };
 
-    shader
+    match compile_shader(gl, typ, &Dialect::detect(caps).adapt(&source, typ)) {
+        Ok(shader) => {
+            debug!("compiled {}", filename);
+            Ok(shader)
+        },
+        Err(log) => Err(format!("failed to compile {}:\n{}", filename, shader::annotate(filename, &source, &log))),
+    }
 }
 
-fn make_program(gl: &GlPtr, vertex_shader: GLuint, fragment_shader: GLuint) -> GLuint {
+// takes the shaders either way
+fn make_program(gl: &GlPtr, vertex_shader: GLuint, fragment_shader: GLuint) -> Result<GLuint, String> {
     let program = gl.create_program();
     gl.attach_shader(program, vertex_shader);
     gl.attach_shader(program, fragment_shader);
     gl.link_program(program);
 
+    // only flagged for now; they go when the program does
+    gl.delete_shader(vertex_shader);
+    gl.delete_shader(fragment_shader);
+
     match gl.get_program_iv(program, gl::LINK_STATUS) {
-        0 => panic!("failed to link shader program: {}", gl.get_program_info_log(program)),
-        _ => ()
-    };
+        0 => {
+            let log = gl.get_program_info_log(program);
+            gl.delete_program(program);
+            Err(format!("failed to link shader program: {}", log))
+        },
+        _ => Ok(program),
+    }
+}
 
-    program
+// the program made of the given shader files. if they don't build, the
+// reason is logged and the error shader stands in, so a mistake made while
+// editing a shader can be fixed and reloaded without restarting.
+fn load_program(gl: &GlPtr, vfs: &dyn Vfs, caps: &Capabilities, vertex: &str, fragment: &str) -> GLuint {
+    let vs = make_shader(gl, vfs, caps, gl::VERTEX_SHADER, vertex);
+    let fs = make_shader(gl, vfs, caps, gl::FRAGMENT_SHADER, fragment);
+    let built = match (vs, fs) {
+        (Ok(vs), Ok(fs)) => make_program(gl, vs, fs),
+        (Ok(s), Err(err)) | (Err(err), Ok(s)) => {
+            gl.delete_shader(s);
+            Err(err)
+        },
+        (Err(err), Err(_)) => Err(err),
+    };
+    match built {
+        Ok(program) => program,
+        Err(err)    => {
+            error!("{}\nusing the error shader", err);
+            let dialect = Dialect::detect(caps);
+            let vs = compile_shader(gl, gl::VERTEX_SHADER, &dialect.adapt(shader::ERROR_VERTEX, gl::VERTEX_SHADER));
+            let fs = compile_shader(gl, gl::FRAGMENT_SHADER, &dialect.adapt(shader::ERROR_FRAGMENT, gl::FRAGMENT_SHADER));
+            match (vs, fs) {
+                (Ok(vs), Ok(fs)) => make_program(gl, vs, fs).expect("error shader doesn't link"),
+                (Err(log), _) | (_, Err(log)) => panic!("error shader doesn't compile: {}", log),
+            }
+        },
+    }
 }
 
-static VERTEX_BUFFER_DATA: [GLfloat; 8] = [
+// two triangles with a corner each, the way an importer hands over a
+// triangle soup
+static VERTEX_BUFFER_DATA: [GLfloat; 12] = [
     -1.0, -1.0,
      1.0, -1.0,
     -1.0,  1.0,
+
+    -1.0,  1.0,
+     1.0, -1.0,
      1.0,  1.0
 ];
 
-static ELEMENT_BUFFER_DATA: [GLushort; 4] = [ 0, 1, 2, 3 ];
+// the quad as an indexed triangle list, welded and run through the mesh
+// optimizer like any other mesh would be
+fn quad_mesh() -> Mesh {
+    let mut mesh = Mesh {
+        vertices: VERTEX_BUFFER_DATA.to_vec(),
+        vertex_size: 2,
+        indices: (0..6).collect(),
+    };
+    let report = mesh.weld(&[Tolerance { components: 2, epsilon: 1e-5 }]);
+    debug!("quad mesh: {}", report);
+    let (before, after) = mesh.optimize();
+    debug!("quad mesh: {} before optimizing, {} after", before, after);
+    mesh
+}
 
-fn make_resources(gl: &GlPtr) -> Option<Resources> {
-    let program = make_program(
-        gl,
-        make_shader(gl, gl::VERTEX_SHADER, "hello-gl.v.glsl"),
-        make_shader(gl, gl::FRAGMENT_SHADER, "hello-gl.f.glsl")
-    );
+static RESOURCE_FILES: [&str; 4] = [ "hello-gl.v.glsl", "hello-gl.f.glsl", "hello1.bmp", "hello2.bmp" ];
+static DEBUG_DRAW_FILES: [&str; 2] = [ "debug.v.glsl", "debug.f.glsl" ];
+static SCRIPT_FILE: &str = "scripts/update.rhai";
+static PLUGIN_DIR: &str = "plugins";
+static BINDINGS_FILE: &str = "bindings.toml";
+static TEXTURE_FILES: [&str; 2] = [ "hello1.bmp", "hello2.bmp" ];
+
+// decodes and uploads the textures right here, for reloads
+fn load_textures(gl: &GlPtr, vfs: &dyn Vfs) -> [GLuint; 2] {
+    let mut textures = [0; 2];
+    for (t, f) in textures.iter_mut().zip(TEXTURE_FILES.iter()) {
+        let image = match loader::decode_image(vfs, f) {
+            Ok(image) => image,
+            Err(err)  => {
+                warn!("{}, using a placeholder", err);
+                Image::placeholder()
+            },
+        };
+        *t = make_texture(gl, f, &image);
+    }
+    textures
+}
+
+fn make_resources(gl: &GlPtr, vfs: &dyn Vfs, caps: &Capabilities, packing: Packing, textures: [GLuint; 2]) -> Option<Resources> {
+    let program = load_program(gl, vfs, caps, "hello-gl.v.glsl", "hello-gl.f.glsl");
+    label::object(Object::Program, program, "hello-gl");
+
+    let position = gl.get_attrib_location(program, "position");
+    // the corners are all +-1, which halves hold exactly
+    let layout = VertexLayout::new(packing, caps)
+        .attribute_as(position, 2, Format::HalfFloat);
+
+    let mesh = quad_mesh();
+    let mut material = Material::new(Program::reflect(gl, program));
+    material.texture("textures[0]", textures[0], Sampler::default());
+    material.texture("textures[1]", textures[1], Sampler::default());
 
     Some(Resources {
-        vertex_buffer: make_buffer(gl, gl::ARRAY_BUFFER, &VERTEX_BUFFER_DATA),
-        element_buffer: make_buffer(gl, gl::ELEMENT_ARRAY_BUFFER, &ELEMENT_BUFFER_DATA),
-        textures: [
-            make_texture(gl, "hello1.bmp"),
-            make_texture(gl, "hello2.bmp"),
-        ],
-        program: program,
-        uniforms: Uniforms {
-            fade_factor: gl.get_uniform_location(program, "fade_factor"),
-            textures: [
-                gl.get_uniform_location(program, "textures[0]"),
-                gl.get_uniform_location(program, "textures[1]"),
-            ],
-        },
-        attributes: Attributes {
-            position: gl.get_attrib_location(program, "position"),
+        gl: gl.clone(),
+        quad: Geometry {
+            vertex_buffers: layout.pack(&mesh.vertices).iter()
+                .map(|data| Buffer::new(gl, gl::ARRAY_BUFFER, Usage::Static, data).labeled("quad vertices"))
+                .collect(),
+            index_buffer: Buffer::new(gl, gl::ELEMENT_ARRAY_BUFFER, Usage::Static, &mesh.indices).labeled("quad indices"),
+            layout,
+            mode: gl::TRIANGLES,
+            count: mesh.indices.len() as GLsizei,
+            index_type: gl::UNSIGNED_SHORT,
         },
-        fade_factor: 0.0,
+        mesh,
+        textures,
+        material,
     })
 }
 
-fn update_fade_factor(sdl_ctx: &sdl2::Sdl, rsrc: &mut Resources) {
-    let ms = sdl_ctx.timer().unwrap().ticks() as f32;
-    rsrc.fade_factor = ((ms * 0.001).sin() * 0.5 + 0.5) as GLfloat;
+// the bounds of the mesh, in clip space, if a point in window coordinates
+// is over them. only the box is tested, not the triangles inside it. the
+// quad's vertex shader flips y, so going from the window to the quad's own
+// space doesn't.
+fn hover(mesh: &Mesh, window_size: (u32, u32), (x, y): (i32, i32)) -> Option<Aabb> {
+    let (w, h) = window_size;
+    let ray = Ray {
+        origin: [2.0 * x as GLfloat / w as GLfloat - 1.0, 2.0 * y as GLfloat / h as GLfloat - 1.0, -1.0],
+        direction: [0.0, 0.0, 1.0],
+    };
+    let bounds = mesh.bounds();
+    ray.hit_aabb(&bounds)?;
+    Some(Aabb {
+        min: [bounds.min[0], -bounds.max[1], bounds.min[2]],
+        max: [bounds.max[0], -bounds.min[1], bounds.max[2]],
+    })
 }
 
-fn render(gl: &GlPtr, rsrc: &Resources) {
-    gl.use_program(rsrc.program);
+// a ring of spokes with a bright one going round, in clip space
+fn draw_spinner(sdl_ctx: &sdl2::Sdl, window: &sdl2::video::Window, backdrop: &Backdrop, debug_draw: &mut DebugDraw, state: &mut StateCache) {
+    const SPOKES: usize = 12;
+    let t = sdl_ctx.timer().unwrap().ticks() as f32 / 1000.0;
+    let lead = (t * SPOKES as f32) as usize % SPOKES;
+
+    let pass = backdrop.pass(window.drawable_size()).label("spinner");
+    pass.begin(state);
+    backdrop.draw(state);
+    for i in 0..SPOKES {
+        let a = i as f32 / SPOKES as f32 * 2.0 * std::f32::consts::PI;
+        let (s, c) = a.sin_cos();
+        let v = 0.2 + 0.8 * ((i + SPOKES - lead) % SPOKES) as f32 / (SPOKES - 1) as f32;
+        debug_draw.line([0.1 * s, 0.1 * c, 0.0], [0.2 * s, 0.2 * c, 0.0], [v, v, v, 1.0]);
+    }
+    debug_draw.flush(state);
+    pass.end(state);
+}
 
-    gl.uniform_1f(rsrc.uniforms.fade_factor, rsrc.fade_factor);
+// the simulation runs at this fixed rate, however fast frames are drawn
+const TICK_MS: u32 = 1000 / 30;
 
-    gl.active_texture(gl::TEXTURE0);
-    gl.bind_texture(gl::TEXTURE_2D, rsrc.textures[0]);
-    gl.uniform_1i(rsrc.uniforms.textures[0], 0);
+fn fade_factor_at(ms: u64) -> GLfloat {
+    ((ms as f32 * 0.001).sin() * 0.5 + 0.5) as GLfloat
+}
 
-    gl.active_texture(gl::TEXTURE1);
-    gl.bind_texture(gl::TEXTURE_2D, rsrc.textures[1]);
-    gl.uniform_1i(rsrc.uniforms.textures[1], 1);
+fn render<'a>(rsrc: &'a mut Resources, backdrop: &'a Backdrop, fade_factor: GLfloat) -> CommandBuffer<'a> {
+    rsrc.material.set("fade_factor", &fade_factor);
+    let rsrc: &'a Resources = rsrc;
+
+    let mut cmds = CommandBuffer::new();
+    backdrop.record(&mut cmds);
+    DrawItem {
+        geometry: &rsrc.quad,
+        material: &rsrc.material,
+        pipeline: PipelineState::default(),
+    }.record(&mut cmds);
+    cmds
+}
+
+struct Options {
+    packing: Packing,
+    gl_info: bool,
+    samples: u8,
+    srgb: bool,
+    gles: bool,
+    display: Option<i32>,
+    gpu: GpuPreference,
+    asset_dir: Option<String>,
+    pak: Option<String>,
+    trace_gl: Option<gl_trace::Request>,
+    background: Background,
+}
 
-    gl.bind_buffer(gl::ARRAY_BUFFER, rsrc.vertex_buffer);
-    gl.vertex_attrib_pointer_f32(
-        rsrc.attributes.position as GLuint,
-        2,
-        false,
-        (mem::size_of::<GLuint>()*2) as GLsizei,
-        0);
-    gl.enable_vertex_attrib_array(rsrc.attributes.position as GLuint);
+fn usage() -> ! {
+    println!("usage: hello-gl [--gl-info] [--separate-attribs] [--msaa N] [--srgb]\n                [--gles] [--display N] [--gpu discrete|integrated]\n                [--asset-dir DIR] [--assets FILE.pak]\n                [--trace-gl frame=N[,timing]] [--background #rrggbb[,#rrggbb]]");
+    std::process::exit(1);
+}
+
+fn parse_options() -> Options {
+    let mut opts = Options {
+        packing: Packing::Interleaved,
+        gl_info: false,
+        samples: 0,
+        srgb: false,
+        gles: false,
+        display: None,
+        gpu: GpuPreference::Default,
+        asset_dir: None,
+        pak: None,
+        trace_gl: None,
+        background: Background::default(),
+    };
 
-    gl.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, rsrc.element_buffer);
-    gl.draw_elements(gl::TRIANGLE_STRIP, 4, gl::UNSIGNED_SHORT, 0);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--separate-attribs" => opts.packing = Packing::Separate,
+            "--gl-info"          => opts.gl_info = true,
+            "--msaa"             => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => opts.samples = n,
+                None    => usage(),
+            },
+            "--srgb"             => opts.srgb = true,
+            "--gles"             => opts.gles = true,
+            "--display"          => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => opts.display = Some(n),
+                None    => usage(),
+            },
+            "--gpu"              => match args.next().as_deref() {
+                Some("discrete")   => opts.gpu = GpuPreference::Discrete,
+                Some("integrated") => opts.gpu = GpuPreference::Integrated,
+                _                  => usage(),
+            },
+            "--asset-dir"        => match args.next() {
+                Some(d) => opts.asset_dir = Some(d),
+                None    => usage(),
+            },
+            "--assets"           => match args.next() {
+                Some(f) => opts.pak = Some(f),
+                None    => usage(),
+            },
+            "--background"       => match args.next().as_deref().and_then(Background::parse) {
+                Some(b) => opts.background = b,
+                None    => usage(),
+            },
+            "--trace-gl"         => match args.next().as_deref().and_then(gl_trace::parse) {
+                Some(r) => opts.trace_gl = Some(r),
+                None    => usage(),
+            },
+            _ => usage(),
+        }
+    }
 
-    gl.disable_vertex_attrib_array(rsrc.attributes.position as GLuint);
+    opts
 }
 
 #[allow(unused_variables)]
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    fatal::install_hook();
+    if env::args().nth(1).as_deref() == Some("bake") {
+        let files: Vec<&str> = RESOURCE_FILES.iter().chain(DEBUG_DRAW_FILES.iter()).chain(background::SHADER_FILES.iter())
+            .chain([SCRIPT_FILE, BINDINGS_FILE].iter()).cloned().collect();
+        bake::main(&files);
+        return;
+    }
+    let mut profiler = profile::Profiler::install();
+    let opts = parse_options();
+    opts.gpu.apply();
+
     let sdl_ctx = match sdl2::init() {
         Ok(ctx)  => ctx,
         Err(err) => panic!("failed to create SDL context: {}", err),
@@ -203,47 +453,327 @@ fn main() {
     let video_subsystem = sdl_ctx.video().unwrap();
     let gl_attr = video_subsystem.gl_attr();
 
-    gl_attr.set_red_size(8);
-    gl_attr.set_green_size(8);
-    gl_attr.set_blue_size(8);
-    gl_attr.set_depth_size(24);
-    gl_attr.set_double_buffer(true);
-
-    let window = match video_subsystem.window("Hello GL!", 400, 300)
-        .position_centered().opengl().build() {
-        Ok(window) => window,
-        Err(err)   => panic!("failed to create window: {}", err),
+    let mut builder = video_subsystem.window("Hello GL!", 400, 300);
+    match opts.display {
+        Some(n) => {
+            let (x, y) = display::centered_on(&video_subsystem, n, 400, 300);
+            builder.position(x, y)
+        },
+        None    => builder.position_centered(),
+    }.opengl();
+    if opts.gl_info {
+        builder.hidden();
+    }
+    let requested = Config {
+        es: if opts.gles { Some(3) } else { None },
+        samples: opts.samples,
+        srgb: opts.srgb,
+        depth: 24,
+        stencil: 8,
     };
+    let (window, gl_context, report) = context::create(&video_subsystem, &builder, requested);
+    if report.is_degraded() {
+        warn!("{}", report);
+    }
 
-    let gl_context = match window.gl_create_context() {
-        Ok(ctx)  => ctx,
-        Err(err) => panic!("failed to create GL context: {}", err),
+    let gl = unsafe {
+        let load = |s: &str| mem::transmute(video_subsystem.gl_get_proc_address(s));
+        if report.actual.es.is_some() { gl::GlesFns::load_with(load) } else { gl::GlFns::load_with(load) }
+    };
+    let (gl, gl_trace) = match opts.trace_gl {
+        Some(request) => {
+            let (gl, trace) = GlTrace::wrap(gl, request);
+            (gl, Some(trace))
+        },
+        None          => (gl, None),
     };
 
-    let gl = unsafe { gl::GlFns::load_with(|s| {
-        mem::transmute(video_subsystem.gl_get_proc_address(s))
-    })};
+    let caps = Capabilities::detect(&gl);
+    label::init(&caps);
+    // GLES encodes into an sRGB framebuffer by itself, desktop GL has to
+    // be told to
+    if report.actual.srgb && !caps.es {
+        gl.enable(gl::FRAMEBUFFER_SRGB);
+    }
+
+    let shown_on = window.display_index().unwrap_or(0) as usize;
+    if let Some(d) = display::describe(&video_subsystem).get(shown_on) {
+        info!("{}", d);
+    }
+    info!("renderer: {}, GL {}.{}{}", gl.get_string(gl::RENDERER),
+          caps.version.0, caps.version.1, if caps.es { " ES" } else { "" });
+
+    if opts.gl_info {
+        info::print_gl_info(&gl, &caps, &gl_attr);
+        return;
+    }
 
-    let mut rsrc = match make_resources(&gl) {
+    let pak = opts.pak.as_ref().map(|f| match vfs::Pak::open(f) {
+        Ok(p)    => p,
+        Err(err) => panic!("couldn't mount {}: {}", f, err),
+    });
+    let vfs: Arc<dyn Vfs> = Arc::new(vfs::default(opts.asset_dir.as_deref(), pak));
+    let mut state = StateCache::new(&gl, &caps);
+    let bindings = Bindings::load(&*vfs, BINDINGS_FILE);
+    let mut event_pump = sdl_ctx.event_pump().unwrap();
+    let mut debug_draw = DebugDraw::new(&gl, &*vfs, &caps);
+    let mut backdrop = Backdrop::new(&gl, &*vfs, &caps, opts.background);
+
+    // textures are decoded on worker threads while a spinner keeps the
+    // window responsive. they're uploaded from a shared context on a thread
+    // of its own where the driver allows, here otherwise.
+    let mut loader = Loader::new(vfs.clone());
+    for f in TEXTURE_FILES.iter() {
+        loader.load(f);
+    }
+    let mut uploader = Uploader::new(&window, &caps);
+    // handed to the uploader and not back yet
+    let mut uploading = Vec::new();
+    let mut textures = [0; 2];
+    let mut remaining = TEXTURE_FILES.len();
+    while remaining > 0 {
+        for event in event_pump.poll_iter() {
+            use sdl2::event::Event;
+            match event {
+                Event::Quit {..} => return,
+                Event::KeyDown { keycode: Some(key), .. } if bindings.action(key) == Some(Action::Quit) => return,
+                _ => (),
+            };
+        }
+        let index = |name: &str| TEXTURE_FILES.iter().position(|f| *f == name).unwrap();
+        for (name, result) in loader.poll() {
+            let image = match result {
+                Ok(image) => image,
+                Err(err)  => {
+                    warn!("{}, using a placeholder", err);
+                    Image::placeholder()
+                },
+            };
+            let here = match uploader {
+                Some(ref u) => match u.upload(&name, image) {
+                    Ok(())   => {
+                        uploading.push(name);
+                        None
+                    },
+                    Err(job) => Some(job),
+                },
+                None        => Some((name, image)),
+            };
+            if let Some((name, image)) = here {
+                textures[index(&name)] = make_texture(&gl, &name, &image);
+                remaining -= 1;
+            }
+        }
+        let lost = match uploader {
+            Some(ref u) => match u.poll(&gl) {
+                Ok(done) => {
+                    for (name, texture) in done {
+                        uploading.retain(|n| *n != name);
+                        textures[index(&name)] = texture;
+                        remaining -= 1;
+                    }
+                    false
+                },
+                Err(_)   => true,
+            },
+            None        => false,
+        };
+        if lost {
+            // its panic has been logged; what it had is loaded again and
+            // uploaded here
+            warn!("upload thread died, uploading {} texture(s) on the main thread", uploading.len());
+            uploader = None;
+            for name in uploading.drain(..) {
+                loader.load(&name);
+            }
+        }
+        draw_spinner(&sdl_ctx, &window, &backdrop, &mut debug_draw, &mut state);
+        window.gl_swap_window();
+    }
+    drop(uploader);
+
+    let mut rsrc = match make_resources(&gl, &*vfs, &caps, opts.packing, textures) {
         Some(r) => r,
         None    => panic!("failed to load resources"),
     };
+    // uploads bound buffers and textures behind the cache's back
+    state.invalidate();
 
-    let mut event_pump = sdl_ctx.event_pump().unwrap();
+    let mut script = Script::load(&*vfs, SCRIPT_FILE);
+    let mut plugins = Plugins::scan(&*vfs, PLUGIN_DIR);
+
+    let mut watcher = match assets::Watcher::new() {
+        Ok(w)    => Some(w),
+        Err(err) => {
+            warn!("asset reloading disabled: {}", err);
+            None
+        },
+    };
+    if let Some(ref mut w) = watcher {
+        let script_file = if script.is_some() { Some(&SCRIPT_FILE) } else { None };
+        let shaders = RESOURCE_FILES.iter().chain(DEBUG_DRAW_FILES.iter()).chain(background::SHADER_FILES.iter());
+        for name in shaders.chain(script_file) {
+            if let Err(err) = vfs.watch(w, name) {
+                warn!("not watching {} for changes: {}", name, err);
+            }
+        }
+    }
+
+    let renderdoc = RenderDoc::attach();
+
+    let mut dump_commands = false;
+    let mut show_debug = false;
+    // in window coordinates, while it's over the window
+    let mut cursor = None;
+    let mut reload_all = false;
+
+    let mut timer = sdl_ctx.timer().unwrap();
+    let mut clock = FixedStep::new(TICK_MS, timer.ticks());
+    let mut fade_factor = Interpolated::new(fade_factor_at(0));
 
     'main: loop {
-        'event: for event in event_pump.poll_iter() {
-            use sdl2::event::Event;
+        let _frame = info_span!("frame").entered();
+        if let Some(ref t) = gl_trace {
+            t.next_frame();
+        }
 
-            match event {
-                Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'main,
-                _ => (),
+        {
+            let _span = info_span!("events").entered();
+            'event: for event in event_pump.poll_iter() {
+                use sdl2::event::Event;
+
+                match event {
+                    Event::Quit {..} => break 'main,
+                    Event::KeyDown { keycode: Some(key), .. } => match bindings.action(key) {
+                        Some(Action::Quit)         => break 'main,
+                        Some(Action::DumpCommands) => dump_commands = true,
+                        Some(Action::ToggleTrace)  => profiler.toggle(),
+                        Some(Action::ReloadAll)    => reload_all = true,
+                        Some(Action::ToggleDebug)  => show_debug = !show_debug,
+                        Some(Action::ShowMemory)   => info!("GPU memory:\n{}", memory::report(&gl, &caps)),
+                        Some(Action::CaptureFrame) => match renderdoc {
+                            Some(ref r) => {
+                                r.trigger_capture();
+                                info!("capturing the next frame in RenderDoc ({} so far)", r.captures());
+                            },
+                            None        => warn!("not running under RenderDoc, or built without the renderdoc feature"),
+                        },
+                        None                       => (),
+                    },
+                    Event::MouseMotion { x, y, .. } => cursor = Some((x, y)),
+                    Event::Window { win_event: sdl2::event::WindowEvent::Leave, .. } => cursor = None,
+                    _ => (),
+                };
+            }
+        }
+
+        {
+            let _span = info_span!("update").entered();
+            if reload_all {
+                info!("reloading everything");
+                // drop the old objects first, so this also exercises a full
+                // teardown and rebuild rather than overlapping the two
+                drop(rsrc);
+                drop(debug_draw);
+                drop(backdrop);
+                rsrc = make_resources(&gl, &*vfs, &caps, opts.packing, load_textures(&gl, &*vfs)).expect("failed to reload resources");
+                debug_draw = DebugDraw::new(&gl, &*vfs, &caps);
+                backdrop = Backdrop::new(&gl, &*vfs, &caps, opts.background);
+                state.invalidate();
+                reload_all = false;
+            }
+            if let Some(ref mut w) = watcher {
+                let changed = w.changed();
+                let touched = |files: &[&str]| changed.iter().any(|c| files.iter().any(|f| c.ends_with(f)));
+                if touched(&RESOURCE_FILES) {
+                    info!("reloading resources");
+                    rsrc = make_resources(&gl, &*vfs, &caps, opts.packing, load_textures(&gl, &*vfs)).expect("failed to reload resources");
+                    state.invalidate();
+                }
+                if touched(&DEBUG_DRAW_FILES) {
+                    info!("reloading debug draw shaders");
+                    debug_draw = DebugDraw::new(&gl, &*vfs, &caps);
+                    state.invalidate();
+                }
+                if touched(&background::SHADER_FILES) {
+                    info!("reloading background shaders");
+                    backdrop = Backdrop::new(&gl, &*vfs, &caps, opts.background);
+                    state.invalidate();
+                }
+                if touched(&[SCRIPT_FILE]) {
+                    if let Some(ref mut s) = script {
+                        s.reload(&*vfs);
+                    }
+                }
+            }
+            let held: Vec<String> = match script {
+                Some(_) => event_pump.keyboard_state().pressed_scancodes()
+                    .filter_map(sdl2::keyboard::Keycode::from_scancode)
+                    .map(|k| k.name())
+                    .collect(),
+                None    => Vec::new(),
             };
+            for _ in 0..clock.advance(timer.ticks()) {
+                let ms = clock.time_ms();
+                let update = match script {
+                    Some(ref mut s) => s.update(ms as f64 * 0.001, &held),
+                    None            => script::Update::default(),
+                };
+                fade_factor.set(update.fade_factor.unwrap_or_else(|| fade_factor_at(ms)));
+                plugins.update(ms as f64 * 0.001);
+            }
         }
 
-        update_fade_factor(&sdl_ctx, &mut rsrc);
-        render(&gl, &rsrc);
+        {
+            let _span = info_span!("main pass").entered();
+            let pass = backdrop.pass(window.drawable_size()).label("main pass");
+            let cmds = render(&mut rsrc, &backdrop, fade_factor.at(clock.alpha()));
+            if dump_commands {
+                print!("{}", cmds);
+                dump_commands = false;
+            }
+            pass.begin(&mut state);
+            cmds.execute(&mut state);
+            pass.end(&mut state);
+        }
 
-        window.gl_swap_window();
+        if !plugins.is_empty() {
+            let _span = info_span!("plugins").entered();
+            let (width, height) = window.drawable_size();
+            label::push_group("plugins");
+            plugins.render(width, height);
+            label::pop_group();
+            state.invalidate_all();
+        }
+
+        if show_debug {
+            let _span = info_span!("debug pass").entered();
+            let pass = RenderPass::new(window.drawable_size()).label("debug pass");
+            pass.begin(&mut state);
+            debug_draw.grid(2.0, 8, [0.5, 0.5, 0.5, 1.0]);
+            debug_draw.axis(&debug_draw::IDENTITY);
+            debug_draw.point([0.0, 0.0, 0.0], [1.0, 1.0, 0.0, 1.0]);
+            if let Some(bounds) = cursor.and_then(|p| hover(&rsrc.mesh, window.size(), p)) {
+                debug_draw.aabb(&bounds, [1.0, 1.0, 1.0, 1.0]);
+            }
+            debug_draw.flush(&mut state);
+            pass.end(&mut state);
+        }
+
+        {
+            let _span = info_span!("swap").entered();
+            window.gl_swap_window();
+        }
+    }
+
+    // GL objects have to go while their context is still current, so don't
+    // leave it to the end of scope
+    drop(plugins);
+    drop(debug_draw);
+    drop(backdrop);
+    drop(rsrc);
+    match gl.get_error() {
+        gl::NO_ERROR => debug!("GL objects released cleanly"),
+        err          => warn!("GL error 0x{:x} releasing GL objects", err),
     }
 }
@@ -0,0 +1,54 @@
+use gleam::gl;
+use sdl2::video::gl_attr::GLAttr;
+
+use GlPtr;
+use caps::Capabilities;
+
+const NUM_COMPRESSED_TEXTURE_FORMATS: u32 = 0x86A2;
+
+// well-known compressed texture families and the extensions that provide them
+static COMPRESSED_FAMILIES: [(&str, &[&str]); 6] = [
+    ("S3TC/DXT", &["GL_EXT_texture_compression_s3tc", "GL_EXT_texture_compression_dxt1", "GL_ANGLE_texture_compression_dxt1"]),
+    ("RGTC",     &["GL_ARB_texture_compression_rgtc", "GL_EXT_texture_compression_rgtc"]),
+    ("ETC1",     &["GL_OES_compressed_ETC1_RGB8_texture"]),
+    ("ETC2/EAC", &["GL_ARB_ES3_compatibility"]),
+    ("PVRTC",    &["GL_IMG_texture_compression_pvrtc"]),
+    ("ASTC",     &["GL_KHR_texture_compression_astc_ldr", "GL_OES_texture_compression_astc"]),
+];
+
+pub fn print_gl_info(gl: &GlPtr, caps: &Capabilities, attr: &GLAttr) {
+    println!("GL_VENDOR:                   {}", gl.get_string(gl::VENDOR));
+    println!("GL_RENDERER:                 {}", gl.get_string(gl::RENDERER));
+    println!("GL_VERSION:                  {}", gl.get_string(gl::VERSION));
+    println!("GL_SHADING_LANGUAGE_VERSION: {}", gl.get_string(gl::SHADING_LANGUAGE_VERSION));
+    println!();
+
+    println!("GL_MAX_TEXTURE_SIZE:         {}", caps.max_texture_size);
+    println!("GL_MAX_TEXTURE_IMAGE_UNITS:  {}", caps.max_texture_units);
+    println!("GL_MAX_VERTEX_ATTRIBS:       {}", caps.max_vertex_attribs);
+    println!();
+
+    let families: Vec<&str> = COMPRESSED_FAMILIES.iter()
+        .filter(|&&(_, names)| names.iter().any(|n| caps.has_extension(n)))
+        .map(|&(family, _)| family)
+        .collect();
+    println!("compressed texture formats:  {} ({})",
+             gl.get_integer_v(NUM_COMPRESSED_TEXTURE_FORMATS),
+             if families.is_empty() { "none known".to_string() } else { families.join(", ") });
+    println!();
+
+    println!("framebuffer: R{}G{}B{}A{} depth {} stencil {}, {} buffered, {} samples",
+             attr.red_size(), attr.green_size(), attr.blue_size(), attr.alpha_size(),
+             attr.depth_size(), attr.stencil_size(),
+             if attr.double_buffer() { "double" } else { "single" },
+             attr.multisample_samples());
+    println!();
+
+    // sorted, since the set has no order of its own
+    let mut exts: Vec<&String> = caps.extensions.iter().collect();
+    exts.sort();
+    println!("{} extensions:", exts.len());
+    for e in exts {
+        println!("    {}", e);
+    }
+}
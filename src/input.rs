@@ -0,0 +1,106 @@
+use sdl2::keyboard::Keycode;
+use std::collections::HashMap;
+use std::io::Read;
+use toml;
+
+use vfs::Vfs;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    DumpCommands,
+    ToggleTrace,
+    ToggleDebug,
+    ReloadAll,
+    ShowMemory,
+    CaptureFrame,
+}
+
+// config name and default keys (SDL key names) of each action
+static ACTIONS: [(Action, &str, &[&str]); 7] = [
+    (Action::Quit,         "quit",          &["Escape"]),
+    (Action::DumpCommands, "dump_commands", &["F2"]),
+    (Action::ToggleTrace,  "toggle_trace",  &["F3"]),
+    (Action::ToggleDebug,  "toggle_debug",  &["G"]),
+    (Action::ReloadAll,    "reload_all",    &["F9"]),
+    (Action::ShowMemory,   "show_memory",   &["M"]),
+    (Action::CaptureFrame, "capture_frame", &["F11"]),
+];
+
+// maps keys to named actions, so the main loop never looks at a raw key.
+// a bindings file may rebind any action, e.g.
+//
+//     [keys]
+//     quit = ["Escape", "Q"]
+//     toggle_debug = "F1"
+//
+// actions it doesn't mention keep their default keys.
+pub struct Bindings {
+    keys: HashMap<Keycode, Action>,
+}
+
+impl Bindings {
+    pub fn defaults() -> Bindings {
+        let mut b = Bindings { keys: HashMap::new() };
+        for &(action, _, keys) in ACTIONS.iter() {
+            b.bind(action, keys.iter().cloned());
+        }
+        b
+    }
+
+    // defaults overridden by the asset at path, if there is one
+    pub fn load(vfs: &dyn Vfs, path: &str) -> Bindings {
+        let mut b = Bindings::defaults();
+
+        let mut text = String::new();
+        if vfs.open(path).and_then(|mut f| f.read_to_string(&mut text)).is_err() {
+            return b;
+        }
+        let config = match text.parse::<toml::Value>() {
+            Ok(v)    => v,
+            Err(err) => {
+                warn!("ignoring {}: {}", path, err);
+                return b;
+            },
+        };
+        let table = match config.get("keys").and_then(|k| k.as_table()) {
+            Some(t) => t,
+            None    => return b,
+        };
+
+        for (name, value) in table {
+            let action = match ACTIONS.iter().find(|a| a.1 == name) {
+                Some(a) => a.0,
+                None    => {
+                    warn!("{}: no action called {}", path, name);
+                    continue;
+                },
+            };
+            let keys: Vec<&str> = match *value {
+                toml::Value::String(ref s) => vec![s.as_str()],
+                toml::Value::Array(ref a)  => a.iter().filter_map(|k| k.as_str()).collect(),
+                _ => {
+                    warn!("{}: {} should be a key name or a list of them", path, name);
+                    continue;
+                },
+            };
+            b.keys.retain(|_, a| *a != action);
+            b.bind(action, keys.into_iter());
+        }
+        debug!("key bindings loaded from {}", path);
+        b
+    }
+
+    fn bind<'a, I: Iterator<Item = &'a str>>(&mut self, action: Action, keys: I) {
+        for name in keys {
+            match Keycode::from_name(name) {
+                Some(key) => { self.keys.insert(key, action); },
+                None      => warn!("unknown key {} for {:?}", name, action),
+            }
+        }
+    }
+
+    pub fn action(&self, key: Keycode) -> Option<Action> {
+        self.keys.get(&key).cloned()
+    }
+}
@@ -0,0 +1,123 @@
+use gleam::gl;
+use gleam::gl::types::{GLuint, GLenum, GLsizei, GLchar};
+use sdl2::sys;
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::c_void;
+use std::sync::OnceLock;
+
+use caps::Capabilities;
+
+// names for GL objects and groups around passes, so apitrace and RenderDoc
+// show "quad vertices" and "main pass" rather than buffer 3 and a flat list
+// of calls. uses KHR_debug where it's there, EXT_debug_label and
+// EXT_debug_marker otherwise, and does nothing if there's neither.
+//
+// set up once with init(); labels can then be attached from any thread with
+// a context current, like the upload thread.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Object {
+    Buffer,
+    Texture,
+    Program,
+}
+
+// KHR_debug identifiers
+const BUFFER: GLenum = 0x82E0;
+const PROGRAM: GLenum = 0x82E2;
+const DEBUG_SOURCE_APPLICATION: GLenum = 0x824A;
+// EXT_debug_label types
+const BUFFER_OBJECT_EXT: GLenum = 0x9151;
+const PROGRAM_OBJECT_EXT: GLenum = 0x8B40;
+
+type ObjectLabelFn = extern "system" fn(GLenum, GLuint, GLsizei, *const GLchar);
+type PushGroupFn = extern "system" fn(GLenum, GLuint, GLsizei, *const GLchar);
+type PopGroupFn = extern "system" fn();
+type PushMarkerFn = extern "system" fn(GLsizei, *const GLchar);
+
+// gleam has no object labels, and only loads the debug group entry points
+// under names desktop GL doesn't use, so these are looked up directly
+enum Fns {
+    Khr { label: ObjectLabelFn, push: PushGroupFn, pop: PopGroupFn },
+    Ext { label: Option<ObjectLabelFn>, push: Option<PushMarkerFn>, pop: Option<PopGroupFn> },
+}
+
+static FNS: OnceLock<Option<Fns>> = OnceLock::new();
+
+fn lookup(names: &[&str]) -> Option<*mut c_void> {
+    names.iter().map(|name| {
+        let name = CString::new(*name).unwrap();
+        unsafe { sys::SDL_GL_GetProcAddress(name.as_ptr()) }
+    }).find(|f| !f.is_null())
+}
+
+impl Fns {
+    fn load(caps: &Capabilities) -> Option<Fns> {
+        // ES names everything KHR_debug has with a suffix
+        let khr = |name: &str| lookup(&[name, &format!("{}KHR", name)]);
+        unsafe {
+            if caps.at_least((4, 3), (3, 2)) || caps.has_extension("GL_KHR_debug") {
+                return Some(Fns::Khr {
+                    label: mem::transmute::<*mut c_void, ObjectLabelFn>(khr("glObjectLabel")?),
+                    push: mem::transmute::<*mut c_void, PushGroupFn>(khr("glPushDebugGroup")?),
+                    pop: mem::transmute::<*mut c_void, PopGroupFn>(khr("glPopDebugGroup")?),
+                });
+            }
+            let label = caps.has_extension("GL_EXT_debug_label");
+            let marker = caps.has_extension("GL_EXT_debug_marker");
+            if !label && !marker {
+                return None;
+            }
+            let ext = |on: bool, name: &str| if on { lookup(&[name]) } else { None };
+            Some(Fns::Ext {
+                label: ext(label, "glLabelObjectEXT").map(|f| mem::transmute::<*mut c_void, ObjectLabelFn>(f)),
+                push: ext(marker, "glPushGroupMarkerEXT").map(|f| mem::transmute::<*mut c_void, PushMarkerFn>(f)),
+                pop: ext(marker, "glPopGroupMarkerEXT").map(|f| mem::transmute::<*mut c_void, PopGroupFn>(f)),
+            })
+        }
+    }
+}
+
+pub fn init(caps: &Capabilities) {
+    let fns = FNS.get_or_init(|| Fns::load(caps));
+    match *fns {
+        Some(Fns::Khr { .. }) => debug!("labelling GL objects with KHR_debug"),
+        Some(Fns::Ext { .. }) => debug!("labelling GL objects with EXT_debug_label/marker"),
+        None                  => debug!("no debug labels on this context"),
+    }
+}
+
+pub fn object(kind: Object, id: GLuint, name: &str) {
+    let (khr_kind, ext_kind) = match kind {
+        Object::Buffer  => (BUFFER, BUFFER_OBJECT_EXT),
+        Object::Texture => (gl::TEXTURE, gl::TEXTURE),
+        Object::Program => (PROGRAM, PROGRAM_OBJECT_EXT),
+    };
+    let len = name.len() as GLsizei;
+    let name = name.as_ptr() as *const GLchar;
+    match FNS.get() {
+        Some(Some(Fns::Khr { label, .. })) => label(khr_kind, id, len, name),
+        Some(Some(Fns::Ext { label: Some(label), .. })) => label(ext_kind, id, len, name),
+        _ => (),
+    }
+}
+
+// groups nest; every push needs a pop
+pub fn push_group(name: &str) {
+    let len = name.len() as GLsizei;
+    let name = name.as_ptr() as *const GLchar;
+    match FNS.get() {
+        Some(Some(Fns::Khr { push, .. })) => push(DEBUG_SOURCE_APPLICATION, 0, len, name),
+        Some(Some(Fns::Ext { push: Some(push), .. })) => push(len, name),
+        _ => (),
+    }
+}
+
+pub fn pop_group() {
+    match FNS.get() {
+        Some(Some(Fns::Khr { pop, .. })) => pop(),
+        Some(Some(Fns::Ext { pop: Some(pop), .. })) => pop(),
+        _ => (),
+    }
+}
@@ -0,0 +1,156 @@
+use sdl2::rwops::RWops;
+use sdl2::surface::Surface;
+use sdl2::pixels::PixelFormatEnum;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use vfs::Vfs;
+
+// an image already decoded by `hello-gl bake`: these bytes, a little-endian
+// u32 width and height, then the pixels as Image holds them
+const BAKED_MAGIC: &[u8; 8] = b"HGLIMG1\0";
+
+// decoded RGB pixels. rows are padded to 4 bytes, as SDL lays them out and
+// as GL's default unpack alignment expects them.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    // a magenta and black checkerboard, standing in for a texture that
+    // couldn't be loaded
+    pub fn placeholder() -> Image {
+        const SIZE: u32 = 64;
+        let mut pixels = Vec::with_capacity((SIZE * SIZE * 3) as usize);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let on = (x / 8 + y / 8) % 2 == 0;
+                pixels.extend_from_slice(if on { &[255, 0, 255] } else { &[0, 0, 0] });
+            }
+        }
+        Image { width: SIZE, height: SIZE, pixels }
+    }
+
+    pub fn bake(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(16 + self.pixels.len());
+        data.extend_from_slice(BAKED_MAGIC);
+        data.extend_from_slice(&self.width.to_le_bytes());
+        data.extend_from_slice(&self.height.to_le_bytes());
+        data.extend_from_slice(&self.pixels);
+        data
+    }
+
+    fn unbake(data: &[u8]) -> Option<Image> {
+        let u32_at = |i: usize| data.get(i..i + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        let width = u32_at(8)?;
+        let height = u32_at(12)?;
+        let pixels = data[16..].to_vec();
+        let row = (width as usize * 3 + 3) & !3;
+        if pixels.len() != row * height as usize {
+            return None;
+        }
+        Some(Image { width, height, pixels })
+    }
+}
+
+// a BMP, or the baked form of one, which skips the decode altogether
+pub fn decode_image(vfs: &dyn Vfs, filename: &str) -> Result<Image, String> {
+    let data = vfs.read(filename).map_err(|e| e.to_string())?;
+    if data.starts_with(BAKED_MAGIC) {
+        return match Image::unbake(&data) {
+            Some(image) => {
+                debug!("loaded baked {}, {}x{}", filename, image.width, image.height);
+                Ok(image)
+            },
+            None        => Err(format!("baked image {} is the wrong size", filename)),
+        };
+    }
+    let bmp = match RWops::from_bytes(&data).and_then(|mut rw| Surface::load_bmp_rw(&mut rw)) {
+        Ok(s)    => s,
+        Err(err) => return Err(format!("couldn't load {}: {}", filename, err)),
+    };
+    let fmt = Surface::new(1, 1, PixelFormatEnum::RGB24)?.pixel_format();
+    let rgb = match bmp.convert(&fmt) {
+        Ok(s)    => s,
+        Err(err) => return Err(format!("couldn't convert {} to RGB: {}", filename, err)),
+    };
+    debug!("decoded {}, {}x{}", filename, rgb.width(), rgb.height());
+    Ok(Image {
+        width: rgb.width(),
+        height: rgb.height(),
+        pixels: rgb.with_lock(|p| p.to_vec()),
+    })
+}
+
+// decodes files on a few worker threads. the GL upload isn't done here; the
+// thread owning the context collects finished images with poll().
+pub struct Loader {
+    jobs: Option<Sender<String>>,
+    results: Receiver<(String, Result<Image, String>)>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Loader {
+    pub fn new(vfs: Arc<dyn Vfs>) -> Loader {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(2).min(4);
+
+        let (job_tx, job_rx) = channel::<String>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let (result_tx, result_rx) = channel();
+
+        let workers = (0..threads).map(|i| {
+            let jobs = job_rx.clone();
+            let results = result_tx.clone();
+            let vfs = vfs.clone();
+            let worker = thread::Builder::new().name(format!("loader {}", i)).spawn(move || loop {
+                // hold the lock only while taking a job, not while decoding it
+                let job = {
+                    let jobs = jobs.lock().unwrap();
+                    jobs.recv()
+                };
+                let filename = match job {
+                    Ok(f)  => f,
+                    Err(_) => break,
+                };
+                let image = decode_image(&*vfs, &filename);
+                if results.send((filename, image)).is_err() {
+                    break;
+                }
+            });
+            match worker {
+                Ok(w)    => w,
+                Err(err) => panic!("couldn't start loader thread: {}", err),
+            }
+        }).collect();
+
+        Loader {
+            jobs: Some(job_tx),
+            results: result_rx,
+            workers,
+        }
+    }
+
+    pub fn load(&mut self, filename: &str) {
+        if let Some(ref jobs) = self.jobs {
+            jobs.send(filename.to_string()).unwrap();
+        }
+    }
+
+    // whatever finished since the last call, with the name it was loaded as
+    pub fn poll(&mut self) -> Vec<(String, Result<Image, String>)> {
+        self.results.try_iter().collect()
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        // closing the queue stops the workers once it's drained
+        self.jobs = None;
+        for w in self.workers.drain(..) {
+            let _ = w.join();
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use gleam::gl::types::{GLuint, GLint};
+
+use command::{CommandBuffer, RenderCommand, Uniform};
+use program::{Program, TextureUnit, UniformValue};
+use sampler::Sampler;
+
+// a program plus the values and textures it draws with. values are checked
+// against the program as they're set, and recorded in one go by record().
+// which unit each texture goes on is left to StateCache::bind_sampler.
+pub struct Material {
+    pub program: Program,
+    uniforms: Vec<(String, GLint, Uniform)>,
+    textures: Vec<(String, GLint, GLuint, Sampler)>,
+}
+
+impl Material {
+    pub fn new(program: Program) -> Material {
+        Material {
+            program,
+            uniforms: Vec::new(),
+            textures: Vec::new(),
+        }
+    }
+
+    // replaces any earlier value of the same uniform
+    pub fn set<V: UniformValue>(&mut self, name: &str, value: &V) {
+        let (location, uniform) = self.program.resolve(name, value);
+        match self.uniforms.iter_mut().find(|u| u.0 == name) {
+            Some(u) => { u.1 = location; u.2 = uniform; },
+            None    => self.uniforms.push((name.to_string(), location, uniform)),
+        }
+    }
+
+    // the texture the named sampler uniform reads, and how
+    pub fn texture(&mut self, name: &str, texture: GLuint, sampler: Sampler) {
+        // only to check it's a sampler; the unit is picked when drawing
+        let (location, _) = self.program.resolve(name, &TextureUnit(0));
+        match self.textures.iter_mut().find(|t| t.0 == name) {
+            Some(t) => { t.1 = location; t.2 = texture; t.3 = sampler; },
+            None    => self.textures.push((name.to_string(), location, texture, sampler)),
+        }
+    }
+
+    pub fn record<'a>(&'a self, cmds: &mut CommandBuffer<'a>) {
+        cmds.push(RenderCommand::SetProgram(self.program.id));
+        for &(_, location, uniform) in &self.uniforms {
+            cmds.push(RenderCommand::SetUniform(location, uniform));
+        }
+        for &(_, location, texture, sampler) in &self.textures {
+            cmds.push(RenderCommand::BindSampler { location, texture, sampler });
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use gleam::gl::types::{GLint, GLuint, GLenum};
+use sdl2::sys;
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::c_void;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use GlPtr;
+use caps::Capabilities;
+
+// what we've asked the driver to allocate, by kind. these are our own
+// estimates: the driver may pad, keep shadow copies, or not have committed
+// anything yet. counted with atomics since textures are also made on the
+// upload thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    // buffers filled once, like the quad
+    StaticBuffers,
+    // buffers rewritten in place now and then
+    DynamicBuffers,
+    // stream buffers, every region included
+    StreamBuffers,
+    Textures,
+}
+
+static CATEGORIES: [(Category, &str); 4] = [
+    (Category::StaticBuffers,  "static buffers"),
+    (Category::DynamicBuffers, "dynamic buffers"),
+    (Category::StreamBuffers,  "stream buffers"),
+    (Category::Textures,       "textures"),
+];
+
+static BYTES: [AtomicUsize; 4] = [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+
+// texture sizes by name, so deleting one only needs the name
+static TEXTURES: Mutex<Vec<(GLuint, usize)>> = Mutex::new(Vec::new());
+
+pub fn allocated(category: Category, bytes: usize) {
+    BYTES[category as usize].fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub fn freed(category: Category, bytes: usize) {
+    BYTES[category as usize].fetch_sub(bytes, Ordering::Relaxed);
+}
+
+pub fn bytes(category: Category) -> usize {
+    BYTES[category as usize].load(Ordering::Relaxed)
+}
+
+// size of an image with the given bytes per pixel, rows padded to
+// GL_UNPACK_ALIGNMENT's default of 4, summed over mip levels down to 1x1
+// when there's a mip chain
+pub fn image_bytes(width: usize, height: usize, pixel: usize, mipmapped: bool) -> usize {
+    let (mut w, mut h, mut total) = (width, height, 0);
+    loop {
+        total += (w * pixel).div_ceil(4) * 4 * h;
+        if !mipmapped || (w == 1 && h == 1) {
+            return total;
+        }
+        w = (w / 2).max(1);
+        h = (h / 2).max(1);
+    }
+}
+
+pub fn texture_allocated(texture: GLuint, bytes: usize) {
+    TEXTURES.lock().unwrap().push((texture, bytes));
+    allocated(Category::Textures, bytes);
+}
+
+pub fn texture_deleted(texture: GLuint) {
+    let mut textures = TEXTURES.lock().unwrap();
+    if let Some(i) = textures.iter().position(|t| t.0 == texture) {
+        freed(Category::Textures, textures.swap_remove(i).1);
+    }
+}
+
+// GL_NVX_gpu_memory_info, in KiB
+const GPU_MEMORY_INFO_TOTAL_AVAILABLE_MEMORY_NVX: GLenum = 0x9048;
+const GPU_MEMORY_INFO_CURRENT_AVAILABLE_VIDMEM_NVX: GLenum = 0x9049;
+// GL_ATI_meminfo: free KiB, largest free block, free auxiliary, largest
+// auxiliary block
+const TEXTURE_FREE_MEMORY_ATI: GLenum = 0x87FC;
+
+type GetIntegervFn = extern "system" fn(GLenum, *mut GLint);
+
+// the ATI query answers with four values, more than gleam's get_integer_v
+// has room for, so it goes to the driver directly
+fn ati_free_texture_memory() -> Option<GLint> {
+    let name = CString::new("glGetIntegerv").unwrap();
+    let f = unsafe { sys::SDL_GL_GetProcAddress(name.as_ptr()) };
+    if f.is_null() {
+        return None;
+    }
+    let get = unsafe { mem::transmute::<*mut c_void, GetIntegervFn>(f) };
+    let mut info: [GLint; 4] = [0; 4];
+    get(TEXTURE_FREE_MEMORY_ATI, info.as_mut_ptr());
+    Some(info[0])
+}
+
+fn kib(bytes: usize) -> String {
+    format!("{} KiB", bytes.div_ceil(1024))
+}
+
+// our totals, and what the driver says is free where it'll tell us
+pub fn report(gl: &GlPtr, caps: &Capabilities) -> String {
+    let mut out = String::new();
+    let mut total = 0;
+    for &(category, name) in CATEGORIES.iter() {
+        total += bytes(category);
+        out.push_str(&format!("{:>16}: {}\n", name, kib(bytes(category))));
+    }
+    out.push_str(&format!("{:>16}: {}\n", "total", kib(total)));
+
+    if caps.has_extension("GL_NVX_gpu_memory_info") {
+        let available = gl.get_integer_v(GPU_MEMORY_INFO_TOTAL_AVAILABLE_MEMORY_NVX);
+        let free = gl.get_integer_v(GPU_MEMORY_INFO_CURRENT_AVAILABLE_VIDMEM_NVX);
+        out.push_str(&format!("{:>16}: {} KiB of {} KiB free\n", "driver (NVX)", free, available));
+    } else if caps.has_extension("GL_ATI_meminfo") {
+        if let Some(free) = ati_free_texture_memory() {
+            out.push_str(&format!("{:>16}: {} KiB free for textures\n", "driver (ATI)", free));
+        }
+    }
+    out
+}
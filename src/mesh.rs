@@ -0,0 +1,573 @@
+use gleam::gl::types::{GLfloat, GLushort};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::mem;
+
+use collision::Aabb;
+use geom::{self, Vec3};
+
+// post-transform cache size assumed when optimizing. small on purpose: the
+// embedded GPUs we care about have caches of 8-16 entries, and tuning for a
+// small cache costs little on big ones.
+pub const CACHE_SIZE: usize = 12;
+
+// an indexed triangle list with interleaved float vertices
+pub struct Mesh {
+    pub vertices: Vec<GLfloat>,
+    pub vertex_size: usize,
+    pub indices: Vec<GLushort>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    // average cache misses per triangle, 0.5 best, 3.0 worst
+    pub acmr: f32,
+    // bytes of vertex data fetched per byte referenced, 1.0 best
+    pub overfetch: f32,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ACMR {:.3}, overfetch {:.3}", self.acmr, self.overfetch)
+    }
+}
+
+// how close a run of vertex components must be to count as equal when
+// welding, e.g. 3 components at 1e-5 for positions, 2 at 1e-4 for UVs.
+// an epsilon of 0 only merges bit-identical values.
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    pub components: usize,
+    pub epsilon: GLfloat,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WeldReport {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    pub bytes_saved: usize,
+}
+
+impl fmt::Display for WeldReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "welded {} vertices to {}, saving {} bytes",
+               self.vertices_before, self.vertices_after, self.bytes_saved)
+    }
+}
+
+impl Mesh {
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / self.vertex_size
+    }
+
+    // the first three components of vertex i, with any missing ones (for
+    // 2D vertices) taken as 0
+    pub fn position(&self, i: usize) -> Vec3 {
+        let v = &self.vertices[i*self.vertex_size..(i+1)*self.vertex_size];
+        let c = |n: usize| v.get(n).cloned().unwrap_or(0.0);
+        [c(0), c(1), c(2)]
+    }
+
+    // assumes positions are the first three components of each vertex
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_vertices(&self.vertices, self.vertex_size)
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            acmr: acmr(&self.indices, CACHE_SIZE),
+            overfetch: overfetch(&self.indices, self.vertex_count(), self.vertex_size * mem::size_of::<GLfloat>()),
+        }
+    }
+
+    // reorders triangles for the post-transform cache and then for
+    // overdraw, then vertices into first-use order. returns the stats before
+    // and after. tipsify is a heuristic, and can lose to an order that was
+    // already good (a small grid drawn row by row); the triangles are then
+    // left as they were.
+    pub fn optimize(&mut self) -> (Stats, Stats) {
+        let before = self.stats();
+        let indices = optimize_vertex_cache(&self.indices, self.vertex_count(), CACHE_SIZE);
+        let indices = self.optimize_overdraw(&indices, CACHE_SIZE);
+        if acmr(&indices, CACHE_SIZE) <= before.acmr {
+            self.indices = indices;
+        }
+        self.optimize_vertex_fetch();
+        (before, self.stats())
+    }
+
+    // the second half of tipsify: cuts cache-ordered triangles into clusters
+    // wherever a triangle misses the cache on all three vertices, so moving
+    // a whole cluster costs next to nothing in ACMR, then draws the clusters
+    // facing furthest out from the mesh's centre first, since they're the
+    // ones most likely to hide the rest.
+    pub fn optimize_overdraw(&self, indices: &[GLushort], cache_size: usize) -> Vec<GLushort> {
+        let tri_count = indices.len() / 3;
+        if tri_count == 0 {
+            return indices.to_vec();
+        }
+
+        let mut clusters: Vec<(usize, usize)> = Vec::new();
+        for (t, &misses) in triangle_misses(indices, cache_size).iter().enumerate() {
+            match clusters.last_mut() {
+                Some(c) if misses < 3 => c.1 = t + 1,
+                _ => clusters.push((t, t + 1)),
+            }
+        }
+
+        let triangle = |t: usize| {
+            let p = |n: usize| self.position(indices[t*3+n] as usize);
+            (p(0), p(1), p(2))
+        };
+        let centroid = |(a, b, c): (Vec3, Vec3, Vec3)| geom::scale(geom::add(geom::add(a, b), c), 1.0 / 3.0);
+        let mean = |ts: (usize, usize)| {
+            let sum = (ts.0..ts.1).fold([0.0; 3], |s, t| geom::add(s, centroid(triangle(t))));
+            geom::scale(sum, 1.0 / (ts.1 - ts.0) as GLfloat)
+        };
+
+        let centre = mean((0, tri_count));
+        let mut keyed: Vec<(GLfloat, (usize, usize))> = clusters.into_iter().map(|cluster| {
+            // summing unnormalized face normals weights them by area
+            let normal = (cluster.0..cluster.1).fold([0.0; 3], |n, t| {
+                let (a, b, c) = triangle(t);
+                geom::add(n, geom::cross(geom::sub(b, a), geom::sub(c, a)))
+            });
+            (geom::dot(geom::sub(mean(cluster), centre), geom::normalize(normal)), cluster)
+        }).collect();
+        // stable, so clusters that tie (all of a flat mesh) keep their order
+        keyed.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+        keyed.iter().flat_map(|&(_, c)| indices[c.0*3..c.1*3].iter().cloned()).collect()
+    }
+
+    // renumbers vertices in the order the index buffer first touches them,
+    // dropping unreferenced ones, so fetches walk memory linearly.
+    pub fn optimize_vertex_fetch(&mut self) {
+        let mut remap = vec![None; self.vertex_count()];
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        let mut next = 0;
+        for i in self.indices.iter_mut() {
+            let v = *i as usize;
+            let new = match remap[v] {
+                Some(n) => n,
+                None    => {
+                    remap[v] = Some(next);
+                    vertices.extend_from_slice(&self.vertices[v*self.vertex_size..(v+1)*self.vertex_size]);
+                    next += 1;
+                    next - 1
+                },
+            };
+            *i = new;
+        }
+        self.vertices = vertices;
+    }
+
+    // merges each vertex into the first vertex kept so far whose components
+    // all fall within the given tolerances of its own, keeping it if there's
+    // none, and rewrites the index buffer to match. meant to run on freshly
+    // imported meshes before they're uploaded.
+    //
+    // candidates are found through a grid over the first tolerance's
+    // components (the positions) with cells twice its epsilon wide: a match
+    // is then in the vertex's own cell or the neighbouring one on whichever
+    // side it's nearer to, so at most 2^components cells are probed.
+    pub fn weld(&mut self, tolerances: &[Tolerance]) -> WeldReport {
+        let covered: usize = tolerances.iter().map(|t| t.components).sum();
+        if covered != self.vertex_size {
+            panic!("weld tolerances cover {} components, vertices have {}", covered, self.vertex_size);
+        }
+
+        let mut epsilons = Vec::with_capacity(self.vertex_size);
+        for t in tolerances {
+            let len = epsilons.len() + t.components;
+            epsilons.resize(len, t.epsilon);
+        }
+        let hashed = tolerances.first().map_or(0, |t| t.components);
+
+        let before = self.vertex_count();
+        let mut cells: HashMap<Vec<i64>, Vec<usize>> = HashMap::new();
+        let mut remap = Vec::with_capacity(before);
+        let mut vertices: Vec<GLfloat> = Vec::with_capacity(self.vertices.len());
+        for v in self.vertices.chunks(self.vertex_size) {
+            // each hashed component's cell, and the neighbour to probe
+            let cell: Vec<(i64, Option<i64>)> = v[..hashed].iter().zip(&epsilons).map(|(&c, &e)| {
+                if e > 0.0 {
+                    let x = c / (2.0 * e);
+                    let k = x.floor();
+                    (k as i64, Some(if x - k < 0.5 { k as i64 - 1 } else { k as i64 + 1 }))
+                } else {
+                    (i64::from(c.to_bits()), None)
+                }
+            }).collect();
+
+            let within = |kept: usize| {
+                let w = &vertices[kept*self.vertex_size..(kept+1)*self.vertex_size];
+                v.iter().zip(w).zip(&epsilons).all(|((&a, &b), &e)| {
+                    if e > 0.0 { (a - b).abs() <= e } else { a.to_bits() == b.to_bits() }
+                })
+            };
+            let mut found: Option<usize> = None;
+            for probe in 0..1usize << cell.len() {
+                let key: Option<Vec<i64>> = cell.iter().enumerate().map(|(i, &(k, n))| {
+                    if probe & (1 << i) == 0 { Some(k) } else { n }
+                }).collect();
+                if let Some(kept) = key.and_then(|k| cells.get(&k)) {
+                    for &w in kept {
+                        if found.is_none_or(|f| w < f) && within(w) {
+                            found = Some(w);
+                        }
+                    }
+                }
+            }
+
+            let index = match found {
+                Some(w) => w,
+                None    => {
+                    let next = vertices.len() / self.vertex_size;
+                    vertices.extend_from_slice(v);
+                    cells.entry(cell.iter().map(|&(k, _)| k).collect()).or_default().push(next);
+                    next
+                },
+            };
+            remap.push(index as GLushort);
+        }
+
+        for i in self.indices.iter_mut() {
+            *i = remap[*i as usize];
+        }
+        self.vertices = vertices;
+
+        let after = self.vertex_count();
+        WeldReport {
+            vertices_before: before,
+            vertices_after: after,
+            bytes_saved: (before - after) * self.vertex_size * mem::size_of::<GLfloat>(),
+        }
+    }
+}
+
+// "tipsify" from Sander, Nehab & Barczak, "Fast Triangle Reordering for
+// Vertex Locality and Reduced Overdraw" (2007). emits triangles fanning
+// around a vertex, choosing the next fan vertex among those still likely to
+// be in the cache. runs in linear time. the overdraw pass that follows it is
+// Mesh::optimize_overdraw, as it needs the positions.
+pub fn optimize_vertex_cache(indices: &[GLushort], vertex_count: usize, cache_size: usize) -> Vec<GLushort> {
+    let tri_count = indices.len() / 3;
+
+    let mut live = vec![0usize; vertex_count];
+    for &i in indices {
+        live[i as usize] += 1;
+    }
+
+    let mut start = vec![0usize; vertex_count + 1];
+    for v in 0..vertex_count {
+        start[v+1] = start[v] + live[v];
+    }
+    let mut adjacency = vec![0usize; indices.len()];
+    let mut fill = start.clone();
+    for (n, &i) in indices.iter().enumerate() {
+        adjacency[fill[i as usize]] = n / 3;
+        fill[i as usize] += 1;
+    }
+
+    let mut timestamp = vec![0usize; vertex_count];
+    let mut emitted = vec![false; tri_count];
+    let mut dead_end: Vec<usize> = Vec::new();
+    let mut out = Vec::with_capacity(indices.len());
+    let mut time = cache_size + 1;
+    let mut cursor = 0;
+
+    let mut fan = if vertex_count > 0 { Some(0) } else { None };
+    while let Some(f) = fan {
+        let mut candidates = Vec::new();
+
+        for &t in &adjacency[start[f]..start[f+1]] {
+            if emitted[t] {
+                continue;
+            }
+            for &i in &indices[t*3..t*3+3] {
+                let v = i as usize;
+                out.push(i);
+                dead_end.push(v);
+                candidates.push(v);
+                live[v] -= 1;
+                if time - timestamp[v] > cache_size {
+                    timestamp[v] = time;
+                    time += 1;
+                }
+            }
+            emitted[t] = true;
+        }
+
+        // best candidate: one that will still be in the cache after its
+        // remaining triangles are emitted, and has been there longest
+        let mut best = None;
+        let mut priority = 0;
+        for &v in &candidates {
+            if live[v] == 0 {
+                continue;
+            }
+            let age = time - timestamp[v];
+            let p = if age + 2 * live[v] <= cache_size { age + 1 } else { 0 };
+            if best.is_none() || p > priority {
+                best = Some(v);
+                priority = p;
+            }
+        }
+
+        fan = best.or_else(|| {
+            while let Some(v) = dead_end.pop() {
+                if live[v] > 0 {
+                    return Some(v);
+                }
+            }
+            while cursor < vertex_count {
+                cursor += 1;
+                if live[cursor-1] > 0 {
+                    return Some(cursor-1);
+                }
+            }
+            None
+        });
+    }
+
+    out
+}
+
+// how many of each triangle's vertices miss a FIFO cache
+fn triangle_misses(indices: &[GLushort], cache_size: usize) -> Vec<usize> {
+    let mut cache = VecDeque::with_capacity(cache_size);
+    indices.chunks(3).map(|tri| {
+        let mut misses = 0;
+        for i in tri {
+            if !cache.contains(i) {
+                misses += 1;
+                if cache.len() == cache_size {
+                    cache.pop_front();
+                }
+                cache.push_back(*i);
+            }
+        }
+        misses
+    }).collect()
+}
+
+// average cache miss ratio of a triangle list through a FIFO cache
+pub fn acmr(indices: &[GLushort], cache_size: usize) -> f32 {
+    if indices.len() < 3 {
+        return 0.0;
+    }
+    let misses: usize = triangle_misses(indices, cache_size).iter().sum();
+    misses as f32 / (indices.len() / 3) as f32
+}
+
+// bytes pulled through a small cache of 64-byte lines for every byte of
+// vertex data the index buffer references. 1.0 means no overfetch.
+pub fn overfetch(indices: &[GLushort], vertex_count: usize, vertex_bytes: usize) -> f32 {
+    const LINE: usize = 64;
+    const LINES: usize = 16;
+
+    let mut used = vec![false; vertex_count];
+    let mut cache: VecDeque<usize> = VecDeque::with_capacity(LINES);
+    let mut fetched = 0;
+    for &i in indices {
+        let v = i as usize;
+        used[v] = true;
+        for line in (v * vertex_bytes / LINE)..=((v + 1) * vertex_bytes - 1) / LINE {
+            if !cache.contains(&line) {
+                fetched += LINE;
+                if cache.len() == LINES {
+                    cache.pop_front();
+                }
+                cache.push_back(line);
+            }
+        }
+    }
+
+    let referenced = used.iter().filter(|&&u| u).count() * vertex_bytes;
+    if referenced == 0 {
+        return 0.0;
+    }
+    fetched as f32 / referenced as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // n by n quads in the z=0 plane, two triangles each, row by row
+    fn grid(n: usize) -> Mesh {
+        let mut vertices = Vec::new();
+        for y in 0..=n {
+            for x in 0..=n {
+                vertices.extend_from_slice(&[x as GLfloat, y as GLfloat, 0.0]);
+            }
+        }
+        let mut indices = Vec::new();
+        for y in 0..n {
+            for x in 0..n {
+                let i = (y * (n + 1) + x) as GLushort;
+                let row = (n + 1) as GLushort;
+                indices.extend_from_slice(&[i, i + 1, i + row, i + row, i + 1, i + row + 1]);
+            }
+        }
+        Mesh { vertices, vertex_size: 3, indices }
+    }
+
+    // each triangle by its positions, rotated to start at the smallest
+    // index's, so winding is kept but the starting corner doesn't matter
+    fn triangles(mesh: &Mesh) -> Vec<[[i32; 3]; 3]> {
+        let mut tris: Vec<[[i32; 3]; 3]> = mesh.indices.chunks(3).map(|t| {
+            let p = |i: GLushort| {
+                let v = mesh.position(i as usize);
+                [v[0] as i32, v[1] as i32, v[2] as i32]
+            };
+            let mut tri = [p(t[0]), p(t[1]), p(t[2])];
+            let first = (0..3).min_by_key(|&k| tri[k]).unwrap();
+            tri.rotate_left(first);
+            tri
+        }).collect();
+        tris.sort();
+        tris
+    }
+
+    #[test]
+    fn acmr_of_fifo() {
+        // every vertex new: 3 misses per triangle
+        assert_eq!(acmr(&[0, 1, 2, 3, 4, 5], 12), 3.0);
+        // a strip-like pair sharing an edge
+        assert_eq!(acmr(&[0, 1, 2, 2, 1, 3], 12), 2.0);
+        // a cache of 2 forgets 0 before the repeat
+        assert_eq!(acmr(&[0, 1, 2, 0, 1, 2], 2), 3.0);
+    }
+
+    #[test]
+    fn optimize_doesnt_worsen_acmr() {
+        for &n in &[1, 2, 4, 8, 16] {
+            let mut mesh = grid(n);
+            let tris = triangles(&mesh);
+            let (before, after) = mesh.optimize();
+            assert!(after.acmr <= before.acmr, "{}x{} grid: {} before, {} after", n, n, before, after);
+            assert_eq!(triangles(&mesh), tris);
+            assert_eq!(mesh.vertex_count(), (n + 1) * (n + 1));
+        }
+        // and on a grid that big, actually better
+        let mut mesh = grid(16);
+        let (before, after) = mesh.optimize();
+        assert!(after.acmr < before.acmr, "{} before, {} after", before, after);
+    }
+
+    #[test]
+    fn overdraw_draws_outer_clusters_first() {
+        // two facing-up triangles, the lower one first. they share no
+        // vertices, so each is a cluster of its own
+        let mesh = Mesh {
+            vertices: vec![
+                0.0, 0.0, -1.0,  1.0, 0.0, -1.0,  0.0, 1.0, -1.0,
+                0.0, 0.0,  1.0,  1.0, 0.0,  1.0,  0.0, 1.0,  1.0,
+            ],
+            vertex_size: 3,
+            indices: vec![0, 1, 2, 3, 4, 5],
+        };
+        assert_eq!(mesh.optimize_overdraw(&mesh.indices, CACHE_SIZE), vec![3, 4, 5, 0, 1, 2]);
+
+        // a flat mesh has nothing to gain and is left alone
+        let flat = grid(4);
+        assert_eq!(flat.optimize_overdraw(&flat.indices, CACHE_SIZE), flat.indices);
+    }
+
+    fn weld_positions(vertices: Vec<GLfloat>, epsilon: GLfloat) -> (Mesh, WeldReport) {
+        let count = vertices.len() as GLushort;
+        let mut mesh = Mesh { vertices, vertex_size: 1, indices: (0..count).collect() };
+        let report = mesh.weld(&[Tolerance { components: 1, epsilon }]);
+        (mesh, report)
+    }
+
+    #[test]
+    fn weld_across_cell_boundaries() {
+        // straddling a multiple of the cell size, and a multiple of half of
+        // it, which a plain round(c / e) key would split
+        for &(a, b) in &[(1.0399, 1.0401), (1.0449, 1.0451), (-0.0001, 0.0001), (0.0199, 0.0201)] {
+            let (mesh, report) = weld_positions(vec![a, b], 0.01);
+            assert_eq!(report.vertices_after, 1, "{} and {} weren't welded", a, b);
+            assert_eq!(mesh.indices, vec![0, 0]);
+        }
+        let (_, report) = weld_positions(vec![1.0, 1.011], 0.01);
+        assert_eq!(report.vertices_after, 2);
+    }
+
+    #[test]
+    fn weld_merges_into_first_kept() {
+        // b is within tolerance of a, c only of b, which wasn't kept
+        let (mesh, report) = weld_positions(vec![0.0, 0.008, 0.016], 0.01);
+        assert_eq!(report.vertices_after, 2);
+        assert_eq!(mesh.vertices, vec![0.0, 0.016]);
+        assert_eq!(mesh.indices, vec![0, 0, 1]);
+    }
+
+    #[test]
+    fn weld_exact() {
+        let next = GLfloat::from_bits(1.0f32.to_bits() + 1);
+        let (mesh, report) = weld_positions(vec![1.0, next, 1.0, 0.0, -0.0], 0.0);
+        assert_eq!(report.vertices_after, 4);
+        assert_eq!(mesh.indices, vec![0, 1, 0, 2, 3]);
+    }
+
+    #[test]
+    fn weld_split_quad() {
+        // two triangles that don't share their corners
+        let mut mesh = Mesh {
+            vertices: vec![
+                0.0, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0,
+                0.0, 1.000001, 0.0,  1.0, 0.0, 0.0,  1.0, 1.0, 0.0,
+            ],
+            vertex_size: 3,
+            indices: (0..6).collect(),
+        };
+        let tris = triangles(&mesh);
+        let report = mesh.weld(&[Tolerance { components: 3, epsilon: 1e-5 }]);
+        assert_eq!(report.vertices_before, 6);
+        assert_eq!(report.vertices_after, 4);
+        assert_eq!(report.bytes_saved, 2 * 3 * 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 1, 3]);
+        assert_eq!(triangles(&mesh), tris);
+    }
+
+    #[test]
+    fn weld_checks_every_tolerance() {
+        // same position, UVs further apart than their tolerance
+        let mut mesh = Mesh {
+            vertices: vec![
+                0.0, 0.0,  0.5, 0.5,
+                0.0, 0.0,  0.5, 0.6,
+                0.0, 0.0,  0.5, 0.50001,
+            ],
+            vertex_size: 4,
+            indices: vec![0, 1, 2],
+        };
+        let report = mesh.weld(&[Tolerance { components: 2, epsilon: 1e-5 }, Tolerance { components: 2, epsilon: 1e-4 }]);
+        assert_eq!(report.vertices_after, 2);
+        assert_eq!(mesh.indices, vec![0, 1, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cover 2 components")]
+    fn weld_needs_every_component() {
+        let mut mesh = grid(1);
+        mesh.weld(&[Tolerance { components: 2, epsilon: 1e-5 }]);
+    }
+
+    #[test]
+    fn optimize_vertex_fetch_orders_by_first_use() {
+        let mut mesh = Mesh {
+            vertices: vec![0.0, 1.0, 2.0, 3.0],
+            vertex_size: 1,
+            indices: vec![3, 1, 2, 2, 1, 3],
+        };
+        mesh.optimize_vertex_fetch();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 1, 0]);
+        // vertex 0 was never referenced and is gone
+        assert_eq!(mesh.vertices, vec![3.0, 1.0, 2.0]);
+    }
+}
@@ -0,0 +1,128 @@
+use gleam::gl;
+use gleam::gl::types::{GLuint, GLfloat, GLsizei, GLbitfield};
+
+use label;
+use pipeline::PipelineState;
+use state::StateCache;
+
+// where a pass draws and what it clears first. the state its draws use is
+// each draw's own PipelineState. a labelled pass shows up as a group in
+// frame captures.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderPass {
+    pub framebuffer: GLuint,
+    pub size: (u32, u32),
+    pub clear_color: Option<[GLfloat; 4]>,
+    pub clear_depth: Option<GLfloat>,
+    pub label: Option<&'static str>,
+}
+
+impl RenderPass {
+    // into the window, clearing nothing
+    pub fn new(size: (u32, u32)) -> RenderPass {
+        RenderPass {
+            framebuffer: 0,
+            size,
+            clear_color: None,
+            clear_depth: None,
+            label: None,
+        }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn framebuffer(mut self, framebuffer: GLuint) -> RenderPass {
+        self.framebuffer = framebuffer;
+        self
+    }
+
+    pub fn clear_color(mut self, color: [GLfloat; 4]) -> RenderPass {
+        self.clear_color = Some(color);
+        self
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn clear_depth(mut self, depth: GLfloat) -> RenderPass {
+        self.clear_depth = Some(depth);
+        self
+    }
+
+    pub fn label(mut self, name: &'static str) -> RenderPass {
+        self.label = Some(name);
+        self
+    }
+
+    pub fn begin(&self, state: &mut StateCache) {
+        if let Some(name) = self.label {
+            label::push_group(name);
+        }
+        state.bind_framebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        state.viewport(0, 0, self.size.0 as GLsizei, self.size.1 as GLsizei);
+
+        let mut mask: GLbitfield = 0;
+        if let Some(c) = self.clear_color {
+            state.clear_color(c[0], c[1], c[2], c[3]);
+            mask |= gl::COLOR_BUFFER_BIT;
+        }
+        if let Some(d) = self.clear_depth {
+            state.clear_depth(d as f64);
+            // the clear obeys the depth mask like any other write
+            let p = PipelineState { depth_write: true, ..state.pipeline() };
+            state.set_pipeline(&p);
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+        if mask != 0 {
+            state.clear(mask);
+        }
+    }
+
+    pub fn end(&self, state: &mut StateCache) {
+        if self.framebuffer != 0 {
+            state.bind_framebuffer(gl::FRAMEBUFFER, 0);
+        }
+        if self.label.is_some() {
+            label::pop_group();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_gl;
+
+    #[test]
+    fn window_pass() {
+        let gl = fake_gl::load();
+        let mut state = StateCache::new(&gl, &fake_gl::caps());
+        let pass = RenderPass::new((400, 300)).clear_color([0.0, 0.5, 1.0, 1.0]);
+        pass.begin(&mut state);
+        pass.end(&mut state);
+        assert_eq!(fake_gl::take(), vec![
+            format!("glBindFramebuffer(0x{:x}, 0)", gl::FRAMEBUFFER),
+            "glViewport(0, 0, 400, 300)".to_string(),
+            "glClearColor(0, 0.5, 1, 1)".to_string(),
+            format!("glClear(0x{:x})", gl::COLOR_BUFFER_BIT),
+        ]);
+    }
+
+    #[test]
+    fn offscreen_pass_clearing_depth() {
+        let gl = fake_gl::load();
+        let mut state = StateCache::new(&gl, &fake_gl::caps());
+        state.set_pipeline(&PipelineState::default().depth_write(false));
+        fake_gl::take();
+
+        let pass = RenderPass::new((64, 64)).framebuffer(7).clear_depth(1.0);
+        pass.begin(&mut state);
+        pass.end(&mut state);
+        assert_eq!(fake_gl::take(), vec![
+            format!("glBindFramebuffer(0x{:x}, 7)", gl::FRAMEBUFFER),
+            "glViewport(0, 0, 64, 64)".to_string(),
+            "glClearDepth(1)".to_string(),
+            // a clear obeys the depth mask, so writes go back on first
+            "glDepthMask(1)".to_string(),
+            format!("glClear(0x{:x})", gl::DEPTH_BUFFER_BIT),
+            format!("glBindFramebuffer(0x{:x}, 0)", gl::FRAMEBUFFER),
+        ]);
+    }
+}
@@ -0,0 +1,114 @@
+use gleam::gl;
+use gleam::gl::types::{GLint, GLuint, GLenum};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stencil {
+    pub func: GLenum,
+    pub reference: GLint,
+    pub mask: GLuint,
+    pub fail: GLenum,
+    pub depth_fail: GLenum,
+    pub pass: GLenum,
+}
+
+// the fixed-function state a draw needs, as a value. draws name the state
+// they want and the StateCache changes only what differs from the last
+// one, so nothing a pass turns on can leak into the next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineState {
+    // comparison function, if testing at all
+    pub depth_test: Option<GLenum>,
+    pub depth_write: bool,
+    pub cull_face: Option<GLenum>,
+    // source and destination factors
+    pub blend: Option<(GLenum, GLenum)>,
+    pub stencil: Option<Stencil>,
+}
+
+impl Default for PipelineState {
+    // GL's initial state
+    fn default() -> PipelineState {
+        PipelineState {
+            depth_test: None,
+            depth_write: true,
+            cull_face: None,
+            blend: None,
+            stencil: None,
+        }
+    }
+}
+
+// no pass tests depth, culls, blends or uses the stencil yet; the builders
+// for those are only called from the tests
+impl PipelineState {
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn depth_test(mut self, func: GLenum) -> PipelineState {
+        self.depth_test = Some(func);
+        self
+    }
+
+    pub fn depth_write(mut self, on: bool) -> PipelineState {
+        self.depth_write = on;
+        self
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn cull(mut self, face: GLenum) -> PipelineState {
+        self.cull_face = Some(face);
+        self
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn blend(mut self, src: GLenum, dst: GLenum) -> PipelineState {
+        self.blend = Some((src, dst));
+        self
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn alpha_blend(self) -> PipelineState {
+        self.blend(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn stencil(mut self, stencil: Stencil) -> PipelineState {
+        self.stencil = Some(stencil);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STENCIL: Stencil = Stencil {
+        func: gl::EQUAL,
+        reference: 1,
+        mask: 0xff,
+        fail: gl::KEEP,
+        depth_fail: gl::KEEP,
+        pass: gl::REPLACE,
+    };
+
+    #[test]
+    fn builders() {
+        let p = PipelineState::default()
+            .depth_test(gl::LEQUAL)
+            .depth_write(false)
+            .cull(gl::BACK)
+            .alpha_blend()
+            .stencil(STENCIL);
+        assert_eq!(p, PipelineState {
+            depth_test: Some(gl::LEQUAL),
+            depth_write: false,
+            cull_face: Some(gl::BACK),
+            blend: Some((gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)),
+            stencil: Some(STENCIL),
+        });
+    }
+
+    #[test]
+    fn later_builders_win() {
+        let p = PipelineState::default().alpha_blend().blend(gl::ONE, gl::ONE);
+        assert_eq!(p.blend, Some((gl::ONE, gl::ONE)));
+    }
+}
@@ -0,0 +1,146 @@
+use libloading::Library;
+use sdl2::sys;
+use std::env::consts::DLL_EXTENSION;
+use std::ffi::CStr;
+use std::fs;
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+
+use vfs::Vfs;
+
+// bumped whenever Host or Api change shape
+pub const API_VERSION: u32 = 1;
+
+// what the host offers a plugin. a GlPtr can't cross a C ABI, so plugins
+// load their own GL bindings through get_proc_address; they draw into the
+// host's context, which is current whenever they're called.
+#[repr(C)]
+pub struct Host {
+    pub get_proc_address: extern "C" fn(name: *const c_char) -> *const c_void,
+    pub log: extern "C" fn(message: *const c_char),
+}
+
+// the table a plugin library hands back from its entry point,
+//
+//     #[no_mangle]
+//     pub extern "C" fn hello_gl_plugin() -> *const Api
+//
+// init returns the plugin's own state, which is passed back to the other
+// calls. time is simulated seconds; width and height are the drawable size.
+// render may leave any GL state behind (bindings, programs, enabled attrib
+// arrays): the host forgets what it had set with StateCache::invalidate_all()
+// once every plugin has drawn.
+#[repr(C)]
+pub struct Api {
+    pub version: u32,
+    pub name: *const c_char,
+    pub init: extern "C" fn(host: *const Host) -> *mut c_void,
+    pub update: extern "C" fn(state: *mut c_void, time: f64),
+    pub render: extern "C" fn(state: *mut c_void, width: u32, height: u32),
+    pub destroy: extern "C" fn(state: *mut c_void),
+}
+
+const ENTRY_POINT: &[u8] = b"hello_gl_plugin\0";
+
+extern "C" fn get_proc_address(name: *const c_char) -> *const c_void {
+    unsafe { sys::SDL_GL_GetProcAddress(name) as *const c_void }
+}
+
+extern "C" fn log(message: *const c_char) {
+    if !message.is_null() {
+        info!("{}", unsafe { CStr::from_ptr(message) }.to_string_lossy());
+    }
+}
+
+static HOST: Host = Host { get_proc_address, log };
+
+struct Plugin {
+    name: String,
+    api: *const Api,
+    state: *mut c_void,
+    // the api table lives in the library, so this has to outlive it
+    _library: Library,
+}
+
+// demo scenes loaded from the shared libraries in a directory
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+impl Plugins {
+    // the libraries in the asset directory called dir. one that can't be
+    // loaded, or was built against another version of the interface, is
+    // skipped with a warning.
+    pub fn scan(vfs: &dyn Vfs, dir: &str) -> Plugins {
+        let mut plugins = Vec::new();
+        let entries = match vfs.real_path(dir).map(fs::read_dir) {
+            Some(Ok(e)) => e,
+            _           => return Plugins { plugins },
+        };
+        let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path())
+            .filter(|p| p.extension() == Some(DLL_EXTENSION.as_ref()))
+            .collect();
+        paths.sort();
+        for path in paths {
+            match unsafe { load(&path) } {
+                Ok(p)    => {
+                    info!("loaded plugin {} from {}", p.name, path.display());
+                    plugins.push(p);
+                },
+                Err(err) => warn!("skipping plugin {}: {}", path.display(), err),
+            }
+        }
+        Plugins { plugins }
+    }
+
+    pub fn update(&mut self, time: f64) {
+        for p in &self.plugins {
+            let _span = trace_span!("plugin update", plugin = p.name.as_str()).entered();
+            unsafe { ((*p.api).update)(p.state, time) };
+        }
+    }
+
+    // plugins change GL state behind the StateCache's back; call
+    // invalidate_all() on it afterwards
+    pub fn render(&mut self, width: u32, height: u32) {
+        for p in &self.plugins {
+            let _span = trace_span!("plugin render", plugin = p.name.as_str()).entered();
+            unsafe { ((*p.api).render)(p.state, width, height) };
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+}
+
+impl Drop for Plugins {
+    fn drop(&mut self) {
+        // in reverse, in case a later plugin leans on an earlier one
+        for p in self.plugins.drain(..).rev() {
+            unsafe { ((*p.api).destroy)(p.state) };
+        }
+    }
+}
+
+unsafe fn load(path: &Path) -> Result<Plugin, String> {
+    let library = Library::new(path).map_err(|e| e.to_string())?;
+    let api = {
+        let entry = library.get::<extern "C" fn() -> *const Api>(ENTRY_POINT)
+            .map_err(|e| e.to_string())?;
+        entry()
+    };
+    if api.is_null() {
+        return Err("entry point returned null".to_string());
+    }
+    if (*api).version != API_VERSION {
+        return Err(format!("built for plugin API {}, host has {}", (*api).version, API_VERSION));
+    }
+    let name = if (*api).name.is_null() {
+        path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+    } else {
+        CStr::from_ptr((*api).name).to_string_lossy().into_owned()
+    };
+    let state = ((*api).init)(&HOST);
+    Ok(Plugin { name, api, state, _library: library })
+}
@@ -0,0 +1,61 @@
+use std::fs::File;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::io;
+use tracing;
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::{self, Layer};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::SubscriberExt;
+
+// spans are only recorded while a capture is running, so they cost next to
+// nothing the rest of the time
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+// collects the tracing spans around the frame's work into chrome://tracing
+// (or Perfetto) JSON files. captures are started and stopped on request, one
+// file per capture.
+pub struct Profiler {
+    guard: FlushGuard,
+    file: Option<String>,
+}
+
+impl Profiler {
+    pub fn install() -> Profiler {
+        // nothing is written until the first capture starts
+        let (layer, guard) = ChromeLayerBuilder::new()
+            .writer(io::sink())
+            .include_args(true)
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(layer.with_filter(filter_fn(|_| CAPTURING.load(Ordering::Relaxed))));
+        if let Err(err) = tracing::subscriber::set_global_default(subscriber) {
+            warn!("tracing disabled: {}", err);
+        }
+        Profiler { guard, file: None }
+    }
+
+    // starts a capture, or finishes the running one and says where it went
+    pub fn toggle(&mut self) {
+        match self.file.take() {
+            None => {
+                let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                let name = format!("trace-{}.json", secs);
+                match File::create(&name) {
+                    Ok(f) => {
+                        self.guard.start_new(Some(Box::new(f)));
+                        CAPTURING.store(true, Ordering::Relaxed);
+                        info!("tracing to {}", name);
+                        self.file = Some(name);
+                    },
+                    Err(err) => warn!("couldn't create {}: {}", name, err),
+                }
+            },
+            Some(name) => {
+                CAPTURING.store(false, Ordering::Relaxed);
+                self.guard.start_new(Some(Box::new(io::sink())));
+                info!("trace written to {}", name);
+            },
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use gleam::gl;
+use gleam::gl::types::{GLuint, GLint, GLenum, GLfloat};
+use std::collections::HashMap;
+
+use GlPtr;
+use command::Uniform;
+use state::StateCache;
+
+// a value that can be assigned to a uniform, and the GLSL types it fits
+pub trait UniformValue {
+    fn accepts(typ: GLenum) -> bool;
+    fn uniform(&self) -> Uniform;
+}
+
+impl UniformValue for GLfloat {
+    fn accepts(typ: GLenum) -> bool { typ == gl::FLOAT }
+    fn uniform(&self) -> Uniform { Uniform::Float(*self) }
+}
+
+impl UniformValue for GLint {
+    fn accepts(typ: GLenum) -> bool { typ == gl::INT || typ == gl::BOOL }
+    fn uniform(&self) -> Uniform { Uniform::Int(*self) }
+}
+
+impl UniformValue for [GLfloat; 2] {
+    fn accepts(typ: GLenum) -> bool { typ == gl::FLOAT_VEC2 }
+    fn uniform(&self) -> Uniform { Uniform::Vec2(*self) }
+}
+
+// also geom::Vec3
+impl UniformValue for [GLfloat; 3] {
+    fn accepts(typ: GLenum) -> bool { typ == gl::FLOAT_VEC3 }
+    fn uniform(&self) -> Uniform { Uniform::Vec3(*self) }
+}
+
+impl UniformValue for [GLfloat; 4] {
+    fn accepts(typ: GLenum) -> bool { typ == gl::FLOAT_VEC4 }
+    fn uniform(&self) -> Uniform { Uniform::Vec4(*self) }
+}
+
+// column-major 3x3 matrix
+impl UniformValue for [GLfloat; 9] {
+    fn accepts(typ: GLenum) -> bool { typ == gl::FLOAT_MAT3 }
+    fn uniform(&self) -> Uniform { Uniform::Mat3(*self) }
+}
+
+// column-major 4x4 matrix
+impl UniformValue for [GLfloat; 16] {
+    fn accepts(typ: GLenum) -> bool { typ == gl::FLOAT_MAT4 }
+    fn uniform(&self) -> Uniform { Uniform::Mat4(*self) }
+}
+
+// the texture unit a sampler reads from. plain ints aren't accepted for
+// samplers, so a unit can't be confused with some other number.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureUnit(pub GLuint);
+
+impl UniformValue for TextureUnit {
+    fn accepts(typ: GLenum) -> bool { typ == gl::SAMPLER_2D || typ == gl::SAMPLER_CUBE }
+    fn uniform(&self) -> Uniform { Uniform::Int(self.0 as GLint) }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ActiveUniform {
+    location: GLint,
+    typ: GLenum,
+}
+
+// a linked program with its active uniforms, as GL reports them, so values
+// can be set by name and checked against the declared type. setting a
+// uniform of another type is a bug in the caller and panics; setting one
+// the program doesn't have (or the compiler optimized out) does nothing,
+// like GL does with location -1.
+//
+// this doesn't own the program object; whoever created it deletes it.
+pub struct Program {
+    pub id: GLuint,
+    uniforms: HashMap<String, ActiveUniform>,
+}
+
+impl Program {
+    pub fn reflect(gl: &GlPtr, id: GLuint) -> Program {
+        let mut uniforms = HashMap::new();
+        for i in 0..gl.get_program_iv(id, gl::ACTIVE_UNIFORMS) as GLuint {
+            let (size, typ, name) = gl.get_active_uniform(id, i);
+            // arrays come back as "name[0]"; make every element, and the
+            // bare name, addressable
+            let base = name.trim_end_matches("[0]").to_string();
+            for e in 0..size {
+                let element = format!("{}[{}]", base, e);
+                let location = gl.get_uniform_location(id, &element);
+                uniforms.insert(element, ActiveUniform { location, typ });
+            }
+            let location = gl.get_uniform_location(id, &base);
+            uniforms.insert(base, ActiveUniform { location, typ });
+        }
+        Program { id, uniforms }
+    }
+
+    // the location to set and what to set it to, checked as described above
+    pub fn resolve<V: UniformValue>(&self, name: &str, value: &V) -> (GLint, Uniform) {
+        match self.uniforms.get(name) {
+            Some(u) if V::accepts(u.typ) => (u.location, value.uniform()),
+            Some(u) => panic!("uniform {} is of type 0x{:x}, can't set it to {:?}", name, u.typ, value.uniform()),
+            None    => (-1, value.uniform()),
+        }
+    }
+
+    // sets a uniform right away, binding the program first
+    pub fn set<V: UniformValue>(&self, state: &mut StateCache, name: &str, value: &V) {
+        let (location, uniform) = self.resolve(name, value);
+        state.use_program(self.id);
+        uniform.apply(state, location);
+    }
+}
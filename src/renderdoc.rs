@@ -0,0 +1,106 @@
+// RenderDoc's in-application API, for taking captures from inside the demo.
+// RenderDoc has to hook GL before the context exists, so this only attaches
+// to a copy that's already loaded, i.e. when the demo was launched from
+// RenderDoc (or with its library preloaded). built with
+// `--features renderdoc`; without it there's never anything to attach to.
+pub use self::imp::RenderDoc;
+
+#[cfg(feature = "renderdoc")]
+mod imp {
+    use libloading::Library;
+    use std::mem;
+    use std::os::raw::{c_int, c_void};
+    use std::ptr;
+
+    // eRENDERDOC_API_Version_1_1_2, the oldest version with everything
+    // used here
+    const API_VERSION: c_int = 10102;
+
+    // slots in the RENDERDOC_API_1_1_2 function table
+    const GET_NUM_CAPTURES: usize = 13;
+    const TRIGGER_CAPTURE: usize = 15;
+
+    type GetApiFn = unsafe extern "C" fn(c_int, *mut *mut c_void) -> c_int;
+    type GetNumCapturesFn = unsafe extern "C" fn() -> u32;
+    type TriggerCaptureFn = unsafe extern "C" fn();
+
+    pub struct RenderDoc {
+        _library: Library,
+        api: *const *const c_void,
+    }
+
+    #[cfg(target_os = "linux")]
+    fn loaded() -> Option<Library> {
+        use libloading::os::unix;
+        // dlopen's RTLD_NOLOAD, which libloading doesn't export
+        const RTLD_NOLOAD: c_int = 4;
+        unsafe { unix::Library::open(Some("librenderdoc.so"), unix::RTLD_NOW | RTLD_NOLOAD) }.ok().map(Library::from)
+    }
+
+    #[cfg(windows)]
+    fn loaded() -> Option<Library> {
+        libloading::os::windows::Library::open_already_loaded("renderdoc.dll").ok().map(Library::from)
+    }
+
+    #[cfg(not(any(target_os = "linux", windows)))]
+    fn loaded() -> Option<Library> {
+        None
+    }
+
+    impl RenderDoc {
+        pub fn attach() -> Option<RenderDoc> {
+            let library = loaded()?;
+            let mut api: *mut c_void = ptr::null_mut();
+            let ok = unsafe {
+                let get_api = match library.get::<GetApiFn>(b"RENDERDOC_GetAPI\0") {
+                    Ok(f)    => f,
+                    Err(err) => {
+                        warn!("RenderDoc is loaded but has no RENDERDOC_GetAPI: {}", err);
+                        return None;
+                    },
+                };
+                get_api(API_VERSION, &mut api)
+            };
+            if ok != 1 || api.is_null() {
+                warn!("RenderDoc doesn't support API version {}", API_VERSION);
+                return None;
+            }
+            info!("attached to RenderDoc");
+            Some(RenderDoc { _library: library, api: api as *const *const c_void })
+        }
+
+        fn function(&self, slot: usize) -> *const c_void {
+            unsafe { *self.api.add(slot) }
+        }
+
+        // captures the next frame presented
+        pub fn trigger_capture(&self) {
+            let trigger = unsafe { mem::transmute::<*const c_void, TriggerCaptureFn>(self.function(TRIGGER_CAPTURE)) };
+            unsafe { trigger() };
+        }
+
+        pub fn captures(&self) -> u32 {
+            let count = unsafe { mem::transmute::<*const c_void, GetNumCapturesFn>(self.function(GET_NUM_CAPTURES)) };
+            unsafe { count() }
+        }
+    }
+}
+
+#[cfg(not(feature = "renderdoc"))]
+mod imp {
+    pub enum RenderDoc {}
+
+    impl RenderDoc {
+        pub fn attach() -> Option<RenderDoc> {
+            None
+        }
+
+        pub fn trigger_capture(&self) {
+            match *self {}
+        }
+
+        pub fn captures(&self) -> u32 {
+            match *self {}
+        }
+    }
+}
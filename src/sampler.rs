@@ -0,0 +1,206 @@
+use gleam::gl;
+use gleam::gl::types::{GLuint, GLint, GLenum, GLfloat, GLsizei};
+use sdl2::sys;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::c_void;
+
+use GlPtr;
+use caps::Capabilities;
+
+// how a texture is sampled, apart from the texture itself
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Sampler {
+    pub min_filter: GLenum,
+    pub mag_filter: GLenum,
+    pub wrap_s: GLenum,
+    pub wrap_t: GLenum,
+    // 1 is off; clamped to what the driver allows
+    pub anisotropy: u8,
+}
+
+impl Default for Sampler {
+    // what make_texture sets up
+    fn default() -> Sampler {
+        Sampler {
+            min_filter: gl::LINEAR,
+            mag_filter: gl::LINEAR,
+            wrap_s: gl::CLAMP_TO_EDGE,
+            wrap_t: gl::CLAMP_TO_EDGE,
+            anisotropy: 1,
+        }
+    }
+}
+
+// nothing samples a texture more than one way yet; tested below
+#[cfg_attr(not(test), allow(dead_code))]
+impl Sampler {
+    pub fn filter(mut self, min: GLenum, mag: GLenum) -> Sampler {
+        self.min_filter = min;
+        self.mag_filter = mag;
+        self
+    }
+
+    pub fn wrap(mut self, s: GLenum, t: GLenum) -> Sampler {
+        self.wrap_s = s;
+        self.wrap_t = t;
+        self
+    }
+
+    pub fn anisotropy(mut self, n: u8) -> Sampler {
+        self.anisotropy = n.max(1);
+        self
+    }
+}
+
+type GenFn = extern "system" fn(GLsizei, *mut GLuint);
+type DeleteFn = extern "system" fn(GLsizei, *const GLuint);
+type BindFn = extern "system" fn(GLuint, GLuint);
+type ParameterIFn = extern "system" fn(GLuint, GLenum, GLint);
+type ParameterFFn = extern "system" fn(GLuint, GLenum, GLfloat);
+type GetFloatvFn = extern "system" fn(GLenum, *mut GLfloat);
+
+fn lookup(name: &str) -> Option<*mut c_void> {
+    let name = CString::new(name).unwrap();
+    let f = unsafe { sys::SDL_GL_GetProcAddress(name.as_ptr()) };
+    if f.is_null() { None } else { Some(f) }
+}
+
+// gleam's float getter has changed shape between releases, so the one
+// query that needs it goes to the driver directly
+fn max_anisotropy() -> Option<GLfloat> {
+    let get = unsafe { mem::transmute::<*mut c_void, GetFloatvFn>(lookup("glGetFloatv")?) };
+    let mut max = 1.0;
+    get(gl::MAX_TEXTURE_MAX_ANISOTROPY_EXT, &mut max);
+    Some(max)
+}
+
+// gleam doesn't wrap sampler objects, so they're looked up directly
+struct Fns {
+    gen: GenFn,
+    delete: DeleteFn,
+    bind: BindFn,
+    parameter_i: ParameterIFn,
+    parameter_f: ParameterFFn,
+}
+
+impl Fns {
+    fn load() -> Option<Fns> {
+        unsafe {
+            Some(Fns {
+                gen: mem::transmute::<*mut c_void, GenFn>(lookup("glGenSamplers")?),
+                delete: mem::transmute::<*mut c_void, DeleteFn>(lookup("glDeleteSamplers")?),
+                bind: mem::transmute::<*mut c_void, BindFn>(lookup("glBindSampler")?),
+                parameter_i: mem::transmute::<*mut c_void, ParameterIFn>(lookup("glSamplerParameteri")?),
+                parameter_f: mem::transmute::<*mut c_void, ParameterFFn>(lookup("glSamplerParameterf")?),
+            })
+        }
+    }
+}
+
+// applies Samplers to texture units. with sampler objects (GLES3, GL 3.3)
+// each distinct Sampler becomes one object, bound to whichever unit needs
+// it, so a texture can be sampled several ways at once. without them the
+// settings are written into the texture's own parameters, so a texture has
+// one way of being sampled at a time.
+pub struct Samplers {
+    fns: Option<Fns>,
+    max_anisotropy: GLfloat,
+    objects: HashMap<Sampler, GLuint>,
+    // parameters last written to each texture, when there are no objects
+    params: HashMap<GLuint, Sampler>,
+}
+
+impl Samplers {
+    pub fn new(caps: &Capabilities) -> Samplers {
+        let fns = if caps.sampler_objects() { Fns::load() } else { None };
+        let max_anisotropy = if caps.anisotropic_filtering() { max_anisotropy().unwrap_or(1.0) } else { 1.0 };
+        debug!("sampler objects: {}, max anisotropy: {}", fns.is_some(), max_anisotropy);
+        Samplers {
+            fns,
+            max_anisotropy,
+            objects: HashMap::new(),
+            params: HashMap::new(),
+        }
+    }
+
+    // whether the sampler goes with the unit rather than the texture
+    pub fn per_unit(&self) -> bool {
+        self.fns.is_some()
+    }
+
+    // for the given unit, which must be active with texture bound
+    pub fn apply(&mut self, gl: &GlPtr, unit: GLuint, texture: GLuint, sampler: &Sampler) {
+        let anisotropy = (sampler.anisotropy as GLfloat).min(self.max_anisotropy);
+        match self.fns {
+            Some(ref f) => {
+                let object = *self.objects.entry(*sampler).or_insert_with(|| {
+                    let mut s = 0;
+                    (f.gen)(1, &mut s);
+                    (f.parameter_i)(s, gl::TEXTURE_MIN_FILTER, sampler.min_filter as GLint);
+                    (f.parameter_i)(s, gl::TEXTURE_MAG_FILTER, sampler.mag_filter as GLint);
+                    (f.parameter_i)(s, gl::TEXTURE_WRAP_S, sampler.wrap_s as GLint);
+                    (f.parameter_i)(s, gl::TEXTURE_WRAP_T, sampler.wrap_t as GLint);
+                    if anisotropy > 1.0 {
+                        (f.parameter_f)(s, gl::TEXTURE_MAX_ANISOTROPY_EXT, anisotropy);
+                    }
+                    s
+                });
+                (f.bind)(unit, object);
+            },
+            None => {
+                if self.params.get(&texture) == Some(sampler) {
+                    return;
+                }
+                gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, sampler.min_filter as GLint);
+                gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, sampler.mag_filter as GLint);
+                gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, sampler.wrap_s as GLint);
+                gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, sampler.wrap_t as GLint);
+                if self.max_anisotropy > 1.0 {
+                    gl.tex_parameter_f(gl::TEXTURE_2D, gl::TEXTURE_MAX_ANISOTROPY_EXT, anisotropy);
+                }
+                self.params.insert(texture, *sampler);
+            },
+        }
+    }
+
+    // texture names may have been deleted and reused
+    pub fn forget_textures(&mut self) {
+        self.params.clear();
+    }
+}
+
+impl Drop for Samplers {
+    fn drop(&mut self) {
+        if let Some(ref f) = self.fns {
+            for (_, s) in self.objects.drain() {
+                (f.delete)(1, &s);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builders() {
+        let s = Sampler::default().filter(gl::LINEAR_MIPMAP_LINEAR, gl::NEAREST).wrap(gl::REPEAT, gl::MIRRORED_REPEAT).anisotropy(8);
+        assert_eq!(s, Sampler {
+            min_filter: gl::LINEAR_MIPMAP_LINEAR,
+            mag_filter: gl::NEAREST,
+            wrap_s: gl::REPEAT,
+            wrap_t: gl::MIRRORED_REPEAT,
+            anisotropy: 8,
+        });
+        // a different way of sampling the same texture is a different key
+        assert_ne!(s, Sampler::default());
+    }
+
+    #[test]
+    fn anisotropy_is_at_least_one() {
+        assert_eq!(Sampler::default().anisotropy(0).anisotropy, 1);
+    }
+}
@@ -0,0 +1,89 @@
+use gleam::gl::types::GLfloat;
+use rhai::{self, Array, Dynamic, Engine, Map, Scope, AST};
+use std::str;
+
+use vfs::Vfs;
+
+// per-tick behaviour written in rhai. the script defines
+//
+//     fn update(time, keys) { ... }
+//
+// where time is simulated seconds and keys is the names of the keys held
+// down, and returns a map of values to apply, e.g. #{ fade_factor: 0.5 }.
+// anything it leaves out, or any tick where it fails, falls back to the
+// built-in behaviour.
+pub struct Script {
+    engine: Engine,
+    name: String,
+    ast: Option<AST>,
+    scope: Scope<'static>,
+    failed: bool,
+}
+
+// what a tick of the script decided
+#[derive(Default)]
+pub struct Update {
+    pub fade_factor: Option<GLfloat>,
+}
+
+impl Script {
+    // None if there's no script called name among the assets
+    pub fn load(vfs: &dyn Vfs, name: &str) -> Option<Script> {
+        if !vfs.exists(name) {
+            return None;
+        }
+        let mut script = Script {
+            engine: Engine::new(),
+            name: name.to_string(),
+            ast: None,
+            scope: Scope::new(),
+            failed: false,
+        };
+        script.reload(vfs);
+        Some(script)
+    }
+
+    // recompiles the file. a script that doesn't compile leaves the
+    // built-in behaviour in charge until it's fixed.
+    pub fn reload(&mut self, vfs: &dyn Vfs) {
+        let source = vfs.read(&self.name).map_err(|e| e.to_string())
+            .and_then(|s| str::from_utf8(&s).map(str::to_string).map_err(|e| e.to_string()));
+        self.ast = match source.and_then(|s| self.engine.compile(&s).map_err(|e| e.to_string())) {
+            Ok(ast)  => {
+                info!("loaded {}", self.name);
+                Some(ast)
+            },
+            Err(err) => {
+                warn!("{}: {}", self.name, err);
+                None
+            },
+        };
+        self.scope = Scope::new();
+        self.failed = false;
+    }
+
+    pub fn update(&mut self, time: f64, keys: &[String]) -> Update {
+        let ast = match self.ast {
+            Some(ref ast) if !self.failed => ast,
+            _ => return Update::default(),
+        };
+        let keys: Array = keys.iter().map(|k| Dynamic::from(k.clone())).collect();
+        let result: Result<Map, _> = self.engine.call_fn(&mut self.scope, ast, "update", (time, keys));
+        let map = match result {
+            Ok(m)    => m,
+            Err(err) => {
+                // once is enough; it would fail the same way every tick
+                warn!("{}: {}", self.name, err);
+                self.failed = true;
+                return Update::default();
+            },
+        };
+        Update {
+            fade_factor: map.get("fade_factor").and_then(float).map(|f| f as GLfloat),
+        }
+    }
+}
+
+fn float(d: &Dynamic) -> Option<rhai::FLOAT> {
+    d.as_float().ok().or_else(|| d.as_int().ok().map(|i| i as rhai::FLOAT))
+}
@@ -0,0 +1,340 @@
+use gleam::gl;
+use gleam::gl::types::GLenum;
+
+use caps::Capabilities;
+
+// the GLSL flavour shaders get compiled as. sources are written as GLSL 1.10
+// (attribute/varying, gl_FragColor, texture2D) and rewritten to match the
+// context, so one file serves ES 2, ES 3 and desktop core alike.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    Glsl110,
+    Glsl330,
+    Essl100,
+    Essl300,
+}
+
+// stands in for a program whose shaders don't build, so a typo in a shader
+// being edited turns things magenta rather than ending the demo. it takes
+// the attributes every program here uses: position as is, in clip space, and
+// color, which is only read in a way no compiler can rule out, so it stays
+// active for layouts that look it up.
+pub static ERROR_VERTEX: &str = "#version 110
+attribute vec4 position;
+attribute vec4 color;
+varying vec4 error_color;
+void main()
+{
+    gl_Position = position;
+    gl_PointSize = 4.0;
+    error_color = color;
+}
+";
+
+pub static ERROR_FRAGMENT: &str = "#version 110
+varying vec4 error_color;
+void main()
+{
+    gl_FragColor = vec4(1.0, 0.0, 1.0, 1.0);
+    if (error_color.a < -1.0) {
+        gl_FragColor = error_color;
+    }
+}
+";
+
+// stands in for gl_FragColor where that's gone
+const FRAG_OUTPUT: &str = "hello_gl_frag_color";
+
+impl Dialect {
+    pub fn detect(caps: &Capabilities) -> Dialect {
+        match (caps.es, caps.glsl_version >= (3, 0), caps.glsl_version >= (3, 3)) {
+            (true,  true, _)  => Dialect::Essl300,
+            (true,  false, _) => Dialect::Essl100,
+            (false, _, true)  => Dialect::Glsl330,
+            (false, _, false) => Dialect::Glsl110,
+        }
+    }
+
+    fn version(self) -> &'static str {
+        match self {
+            Dialect::Glsl110 => "#version 110",
+            Dialect::Glsl330 => "#version 330 core",
+            Dialect::Essl100 => "#version 100",
+            Dialect::Essl300 => "#version 300 es",
+        }
+    }
+
+    fn modern(self) -> bool {
+        self == Dialect::Glsl330 || self == Dialect::Essl300
+    }
+
+    fn es(self) -> bool {
+        self == Dialect::Essl100 || self == Dialect::Essl300
+    }
+
+    // source in this dialect. the #version line is replaced and anything
+    // added goes on the first line after the directives, so compile errors
+    // still point at the right line of the file.
+    pub fn adapt(self, source: &str, stage: GLenum) -> String {
+        let fragment = stage == gl::FRAGMENT_SHADER;
+
+        let mut prelude = String::new();
+        if self.es() && fragment {
+            prelude.push_str("precision mediump float; ");
+        }
+        if self.modern() && fragment {
+            prelude.push_str(&format!("out vec4 {}; ", FRAG_OUTPUT));
+        }
+
+        let renames: &[(&str, &str)] = match (self.modern(), fragment) {
+            (false, _)    => &[],
+            (true, false) => &[("attribute", "in"), ("varying", "out"), ("texture2D", "texture")],
+            (true, true)  => &[("varying", "in"), ("texture2D", "texture"), ("gl_FragColor", FRAG_OUTPUT)],
+        };
+
+        let mut out = String::with_capacity(source.len() + 64);
+        let mut lines = source.lines().peekable();
+        if lines.peek().is_some_and(|l| l.trim_start().starts_with("#version")) {
+            lines.next();
+        }
+        out.push_str(self.version());
+        out.push('\n');
+        for line in lines {
+            if !prelude.is_empty() && !line.trim_start().starts_with('#') {
+                out.push_str(&prelude);
+                prelude.clear();
+            }
+            out.push_str(&rename_words(line, renames));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// replaces whole identifiers only, so e.g. frag_color_varying is left alone
+fn rename_words(line: &str, renames: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut word = String::new();
+    let flush = |out: &mut String, word: &mut String| {
+        match renames.iter().find(|r| r.0 == word.as_str()) {
+            Some(r) => out.push_str(r.1),
+            None    => out.push_str(word),
+        }
+        word.clear();
+    };
+    for c in line.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+        } else {
+            flush(&mut out, &mut word);
+            out.push(c);
+        }
+    }
+    flush(&mut out, &mut word);
+    out
+}
+
+// the source line a driver's log message is about. drivers agree on little
+// but putting the source string index and line number first, as in
+//
+//     0:12(5): error: ...          (Mesa)
+//     0(12) : error C1008: ...     (NVIDIA)
+//     ERROR: 0:12: ...             (ANGLE and most ES drivers)
+fn log_line_number(message: &str) -> Option<usize> {
+    let start = message.find(|c: char| c.is_ascii_digit())?;
+    let rest = message[start..].trim_start_matches(|c: char| c.is_ascii_digit());
+    let rest = rest.strip_prefix(':').or_else(|| rest.strip_prefix('('))?;
+    let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+// a driver's info log, each message prefixed with file:line and followed by
+// the line it's about. the source is the file as written; adapt() keeps the
+// line numbers of sources that start with #version, as ours all do.
+pub fn annotate(filename: &str, source: &str, log: &str) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut out = String::new();
+    for message in log.lines().map(str::trim).filter(|m| !m.is_empty()) {
+        match log_line_number(message).filter(|&n| n >= 1 && n <= lines.len()) {
+            Some(n) => {
+                out.push_str(&format!("{}:{}: {}\n", filename, n, message));
+                out.push_str(&format!("{:>5} | {}\n", n, lines[n - 1]));
+            },
+            None => {
+                out.push_str(&format!("{}: {}\n", filename, message));
+            },
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn caps(es: bool, glsl_version: (u32, u32)) -> Capabilities {
+        Capabilities {
+            es,
+            version: (3, 0),
+            glsl_version,
+            extensions: HashSet::new(),
+            max_texture_size: 2048,
+            max_texture_units: 8,
+            max_vertex_attribs: 16,
+        }
+    }
+
+    static VERTEX: &str = "#version 110
+attribute vec2 position;
+varying vec2 texcoord;
+void main()
+{
+    texcoord = position * 0.5 + 0.5;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+";
+
+    static FRAGMENT: &str = "#version 110
+#define SCALE 2.0
+uniform sampler2D mytexture2D;
+varying vec2 texcoord;
+void main()
+{
+    gl_FragColor = texture2D(mytexture2D, texcoord * SCALE);
+}
+";
+
+    #[test]
+    fn detect() {
+        assert_eq!(Dialect::detect(&caps(false, (1, 2))), Dialect::Glsl110);
+        assert_eq!(Dialect::detect(&caps(false, (1, 5))), Dialect::Glsl110);
+        // parse_version gives 3.30 as (3, 3)
+        assert_eq!(Dialect::detect(&caps(false, (3, 3))), Dialect::Glsl330);
+        assert_eq!(Dialect::detect(&caps(false, (4, 6))), Dialect::Glsl330);
+        assert_eq!(Dialect::detect(&caps(true, (1, 0))), Dialect::Essl100);
+        assert_eq!(Dialect::detect(&caps(true, (3, 0))), Dialect::Essl300);
+        assert_eq!(Dialect::detect(&caps(true, (3, 2))), Dialect::Essl300);
+    }
+
+    #[test]
+    fn glsl110_is_untouched() {
+        assert_eq!(Dialect::Glsl110.adapt(VERTEX, gl::VERTEX_SHADER), VERTEX);
+        assert_eq!(Dialect::Glsl110.adapt(FRAGMENT, gl::FRAGMENT_SHADER), FRAGMENT);
+    }
+
+    #[test]
+    fn essl100() {
+        assert_eq!(Dialect::Essl100.adapt(VERTEX, gl::VERTEX_SHADER), VERTEX.replace("#version 110", "#version 100"));
+        assert_eq!(Dialect::Essl100.adapt(FRAGMENT, gl::FRAGMENT_SHADER), "#version 100
+#define SCALE 2.0
+precision mediump float; uniform sampler2D mytexture2D;
+varying vec2 texcoord;
+void main()
+{
+    gl_FragColor = texture2D(mytexture2D, texcoord * SCALE);
+}
+");
+    }
+
+    #[test]
+    fn glsl330() {
+        assert_eq!(Dialect::Glsl330.adapt(VERTEX, gl::VERTEX_SHADER), "#version 330 core
+in vec2 position;
+out vec2 texcoord;
+void main()
+{
+    texcoord = position * 0.5 + 0.5;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+");
+        assert_eq!(Dialect::Glsl330.adapt(FRAGMENT, gl::FRAGMENT_SHADER), "#version 330 core
+#define SCALE 2.0
+out vec4 hello_gl_frag_color; uniform sampler2D mytexture2D;
+in vec2 texcoord;
+void main()
+{
+    hello_gl_frag_color = texture(mytexture2D, texcoord * SCALE);
+}
+");
+    }
+
+    #[test]
+    fn essl300() {
+        assert_eq!(Dialect::Essl300.adapt(VERTEX, gl::VERTEX_SHADER),
+                   Dialect::Glsl330.adapt(VERTEX, gl::VERTEX_SHADER).replace("#version 330 core", "#version 300 es"));
+        assert_eq!(Dialect::Essl300.adapt(FRAGMENT, gl::FRAGMENT_SHADER), "#version 300 es
+#define SCALE 2.0
+precision mediump float; out vec4 hello_gl_frag_color; uniform sampler2D mytexture2D;
+in vec2 texcoord;
+void main()
+{
+    hello_gl_frag_color = texture(mytexture2D, texcoord * SCALE);
+}
+");
+    }
+
+    #[test]
+    fn line_numbers_are_kept() {
+        for &dialect in &[Dialect::Glsl110, Dialect::Glsl330, Dialect::Essl100, Dialect::Essl300] {
+            for &(source, stage) in &[(VERTEX, gl::VERTEX_SHADER), (FRAGMENT, gl::FRAGMENT_SHADER)] {
+                let adapted = dialect.adapt(source, stage);
+                assert_eq!(adapted.lines().count(), source.lines().count(), "{:?}", dialect);
+                // main() stays where it was
+                let main = |s: &str| s.lines().position(|l| l == "void main()");
+                assert_eq!(main(&adapted), main(source), "{:?}", dialect);
+            }
+        }
+    }
+
+    #[test]
+    fn without_a_version_line() {
+        assert_eq!(Dialect::Essl300.adapt("void main() {}\n", gl::VERTEX_SHADER), "#version 300 es\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn mesa_log_lines() {
+        assert_eq!(log_line_number("0:7(5): error: `texcord' undeclared"), Some(7));
+    }
+
+    #[test]
+    fn nvidia_log_lines() {
+        assert_eq!(log_line_number("0(7) : error C1008: undefined variable \"texcord\""), Some(7));
+    }
+
+    #[test]
+    fn angle_log_lines() {
+        assert_eq!(log_line_number("ERROR: 0:7: 'texcord' : undeclared identifier"), Some(7));
+        assert_eq!(log_line_number("ERROR: 1 compilation errors.  No code generated."), None);
+    }
+
+    #[test]
+    fn annotated() {
+        let log = "0:7(5): error: `texcord' undeclared\n\n0:7(5): error: type mismatch\n";
+        assert_eq!(annotate("test.f.glsl", FRAGMENT, log), "\
+test.f.glsl:7: 0:7(5): error: `texcord' undeclared
+    7 |     gl_FragColor = texture2D(mytexture2D, texcoord * SCALE);
+test.f.glsl:7: 0:7(5): error: type mismatch
+    7 |     gl_FragColor = texture2D(mytexture2D, texcoord * SCALE);
+");
+    }
+
+    #[test]
+    fn annotated_outside_the_source() {
+        // line 0 or a line past the end comes through without a source line
+        let log = "0:40(1): error: unexpected end of file\nERROR: 0:0: missing main";
+        assert_eq!(annotate("test.f.glsl", FRAGMENT, log), "\
+test.f.glsl: 0:40(1): error: unexpected end of file
+test.f.glsl: ERROR: 0:0: missing main
+");
+    }
+
+    #[test]
+    fn whole_words_only() {
+        let renames = &[("texture2D", "texture"), ("varying", "in")];
+        assert_eq!(rename_words("texture2D(t, uv)", renames), "texture(t, uv)");
+        assert_eq!(rename_words("mytexture2D texture2Dx texture2D_", renames), "mytexture2D texture2Dx texture2D_");
+        assert_eq!(rename_words("varying vec2 varying_uv;", renames), "in vec2 varying_uv;");
+        assert_eq!(rename_words("", renames), "");
+    }
+}
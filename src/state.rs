@@ -0,0 +1,334 @@
+use gleam::gl;
+use gleam::gl::types::{GLuint, GLint, GLenum};
+use std::ops::Deref;
+
+use GlPtr;
+use caps::Capabilities;
+use pipeline::PipelineState;
+use sampler::{Sampler, Samplers};
+
+// texture units tracked; binds to units past this go straight through
+const TEXTURE_UNITS: usize = 8;
+
+// sits in front of the GL context and remembers what's bound, dropping
+// binds that wouldn't change anything. calls it doesn't wrap pass through
+// via Deref. anything that changes bindings behind its back (resource
+// creation, buffer updates) must be followed by invalidate().
+pub struct StateCache {
+    gl: GlPtr,
+    program: Option<GLuint>,
+    array_buffer: Option<GLuint>,
+    element_buffer: Option<GLuint>,
+    active_texture: Option<GLenum>,
+    textures: [Option<GLuint>; TEXTURE_UNITS],
+    unit_samplers: [Option<Sampler>; TEXTURE_UNITS],
+    samplers: Samplers,
+    // for picking a unit to reuse: when each was last handed out, and
+    // which were handed out for the draw being set up
+    unit_used: [u64; TEXTURE_UNITS],
+    unit_clock: u64,
+    pinned_units: u32,
+    attribs: u32,
+    max_attribs: u32,
+    pipeline: Option<PipelineState>,
+}
+
+impl StateCache {
+    pub fn new(gl: &GlPtr, caps: &Capabilities) -> StateCache {
+        StateCache {
+            gl: gl.clone(),
+            program: None,
+            array_buffer: None,
+            element_buffer: None,
+            active_texture: None,
+            textures: [None; TEXTURE_UNITS],
+            unit_samplers: [None; TEXTURE_UNITS],
+            samplers: Samplers::new(caps),
+            unit_used: [0; TEXTURE_UNITS],
+            unit_clock: 0,
+            pinned_units: 0,
+            attribs: 0,
+            max_attribs: caps.max_vertex_attribs.clamp(0, 32) as u32,
+            pipeline: None,
+        }
+    }
+
+    // forget the bindings, so the next call of each kind goes through. the
+    // attrib arrays the cache enabled are disabled, so the next draw starts
+    // from none.
+    pub fn invalidate(&mut self) {
+        self.set_attribs(0);
+        self.program = None;
+        self.array_buffer = None;
+        self.element_buffer = None;
+        self.active_texture = None;
+        self.textures = [None; TEXTURE_UNITS];
+        self.unit_samplers = [None; TEXTURE_UNITS];
+        self.samplers.forget_textures();
+        self.pipeline = None;
+    }
+
+    // invalidate(), for when foreign code (plugins) may also have enabled
+    // attrib arrays the cache never knew about: every array the context has
+    // is disabled.
+    pub fn invalidate_all(&mut self) {
+        self.invalidate();
+        for i in 0..self.max_attribs {
+            self.gl.disable_vertex_attrib_array(i);
+        }
+    }
+
+    pub fn use_program(&mut self, program: GLuint) {
+        if self.program != Some(program) {
+            self.gl.use_program(program);
+            self.program = Some(program);
+        }
+    }
+
+    pub fn bind_buffer(&mut self, target: GLenum, buffer: GLuint) {
+        let cached = match target {
+            gl::ARRAY_BUFFER         => &mut self.array_buffer,
+            gl::ELEMENT_ARRAY_BUFFER => &mut self.element_buffer,
+            _ => {
+                self.gl.bind_buffer(target, buffer);
+                return;
+            },
+        };
+        if *cached != Some(buffer) {
+            self.gl.bind_buffer(target, buffer);
+            *cached = Some(buffer);
+        }
+    }
+
+    pub fn active_texture(&mut self, unit: GLenum) {
+        if self.active_texture != Some(unit) {
+            self.gl.active_texture(unit);
+            self.active_texture = Some(unit);
+        }
+    }
+
+    // binds a 2D texture to the currently active unit
+    pub fn bind_texture(&mut self, texture: GLuint) {
+        let unit = self.active_texture.map(|u| (u - gl::TEXTURE0) as usize);
+        match unit {
+            Some(u) if u < TEXTURE_UNITS => {
+                if self.textures[u] != Some(texture) {
+                    self.gl.bind_texture(gl::TEXTURE_2D, texture);
+                    self.textures[u] = Some(texture);
+                }
+            },
+            _ => self.gl.bind_texture(gl::TEXTURE_2D, texture),
+        }
+    }
+
+    // puts texture on a unit, sampled as sampler says, and points the
+    // sampler uniform at location (of the current program) at it. a unit
+    // already set up that way is reused; otherwise the one used least
+    // recently, skipping any taken for the same draw, since the last
+    // end_draw().
+    pub fn bind_sampler(&mut self, location: GLint, texture: GLuint, sampler: Sampler) {
+        let per_unit = self.samplers.per_unit();
+        let resident = (0..TEXTURE_UNITS).find(|&u| {
+            self.textures[u] == Some(texture) && (!per_unit || self.unit_samplers[u] == Some(sampler))
+        });
+        let unit = match resident {
+            Some(u) => u,
+            None    => match (0..TEXTURE_UNITS).filter(|u| self.pinned_units & (1 << u) == 0)
+                                               .min_by_key(|&u| self.unit_used[u]) {
+                Some(u) => u,
+                None    => panic!("more than {} textures in one draw", TEXTURE_UNITS),
+            },
+        };
+        self.unit_clock += 1;
+        self.unit_used[unit] = self.unit_clock;
+        self.pinned_units |= 1 << unit;
+
+        self.active_texture(gl::TEXTURE0 + unit as GLenum);
+        self.bind_texture(texture);
+        if self.unit_samplers[unit] != Some(sampler) || !per_unit {
+            self.samplers.apply(&self.gl, unit as GLuint, texture, &sampler);
+            self.unit_samplers[unit] = Some(sampler);
+        }
+        self.gl.uniform_1i(location, unit as GLint);
+    }
+
+    // the draw the samplers were bound for has been issued; their units
+    // may be reused
+    pub fn end_draw(&mut self) {
+        self.pinned_units = 0;
+    }
+
+    // the pipeline state last set, or GL's defaults if it isn't known
+    pub fn pipeline(&self) -> PipelineState {
+        self.pipeline.unwrap_or_default()
+    }
+
+    // changes whatever differs from the pipeline state last set. after an
+    // invalidate() every part of it is set again.
+    pub fn set_pipeline(&mut self, p: &PipelineState) {
+        let old = self.pipeline;
+
+        if old.map(|o| o.depth_test.is_some()) != Some(p.depth_test.is_some()) {
+            self.set_enabled(gl::DEPTH_TEST, p.depth_test.is_some());
+        }
+        if let Some(func) = p.depth_test {
+            if old.and_then(|o| o.depth_test) != Some(func) {
+                self.gl.depth_func(func);
+            }
+        }
+        if old.map(|o| o.depth_write) != Some(p.depth_write) {
+            self.gl.depth_mask(p.depth_write);
+        }
+
+        if old.map(|o| o.cull_face.is_some()) != Some(p.cull_face.is_some()) {
+            self.set_enabled(gl::CULL_FACE, p.cull_face.is_some());
+        }
+        if let Some(face) = p.cull_face {
+            if old.and_then(|o| o.cull_face) != Some(face) {
+                self.gl.cull_face(face);
+            }
+        }
+
+        if old.map(|o| o.blend.is_some()) != Some(p.blend.is_some()) {
+            self.set_enabled(gl::BLEND, p.blend.is_some());
+        }
+        if let Some((src, dst)) = p.blend {
+            if old.and_then(|o| o.blend) != Some((src, dst)) {
+                self.gl.blend_func(src, dst);
+            }
+        }
+
+        if old.map(|o| o.stencil.is_some()) != Some(p.stencil.is_some()) {
+            self.set_enabled(gl::STENCIL_TEST, p.stencil.is_some());
+        }
+        if let Some(s) = p.stencil {
+            if old.and_then(|o| o.stencil) != Some(s) {
+                self.gl.stencil_func(s.func, s.reference, s.mask);
+                self.gl.stencil_op(s.fail, s.depth_fail, s.pass);
+            }
+        }
+
+        self.pipeline = Some(*p);
+    }
+
+    fn set_enabled(&self, cap: GLenum, on: bool) {
+        if on {
+            self.gl.enable(cap);
+        } else {
+            self.gl.disable(cap);
+        }
+    }
+
+    // enables exactly the attrib arrays whose bits are set in mask,
+    // disabling any others left on by earlier draws. until told otherwise
+    // it assumes GL's default of all arrays disabled.
+    pub fn set_attribs(&mut self, mask: u32) {
+        let changed = self.attribs ^ mask;
+        for i in 0..32 {
+            if changed & (1 << i) != 0 {
+                if mask & (1 << i) != 0 {
+                    self.gl.enable_vertex_attrib_array(i);
+                } else {
+                    self.gl.disable_vertex_attrib_array(i);
+                }
+            }
+        }
+        self.attribs = mask;
+    }
+}
+
+impl Deref for StateCache {
+    type Target = GlPtr;
+
+    fn deref(&self) -> &GlPtr {
+        &self.gl
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_gl;
+    use pipeline::Stencil;
+
+    fn enable(cap: GLenum, on: bool) -> String {
+        format!("gl{}(0x{:x})", if on { "Enable" } else { "Disable" }, cap)
+    }
+
+    #[test]
+    fn first_pipeline_sets_everything() {
+        let gl = fake_gl::load();
+        let mut state = StateCache::new(&gl, &fake_gl::caps());
+        state.set_pipeline(&PipelineState::default());
+        assert_eq!(fake_gl::take(), vec![
+            enable(gl::DEPTH_TEST, false),
+            "glDepthMask(1)".to_string(),
+            enable(gl::CULL_FACE, false),
+            enable(gl::BLEND, false),
+            enable(gl::STENCIL_TEST, false),
+        ]);
+    }
+
+    #[test]
+    fn only_differences_are_set() {
+        let gl = fake_gl::load();
+        let mut state = StateCache::new(&gl, &fake_gl::caps());
+        state.set_pipeline(&PipelineState::default());
+        fake_gl::take();
+
+        state.set_pipeline(&PipelineState::default());
+        assert!(fake_gl::take().is_empty());
+
+        let blended = PipelineState::default().blend(gl::ONE, gl::ONE);
+        state.set_pipeline(&blended);
+        assert_eq!(fake_gl::take(), vec![
+            enable(gl::BLEND, true),
+            format!("glBlendFunc(0x{:x}, 0x{:x})", gl::ONE, gl::ONE),
+        ]);
+
+        // still enabled, so only the factors change
+        state.set_pipeline(&blended.alpha_blend().depth_write(false));
+        assert_eq!(fake_gl::take(), vec![
+            "glDepthMask(0)".to_string(),
+            format!("glBlendFunc(0x{:x}, 0x{:x})", gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+        ]);
+
+        state.set_pipeline(&PipelineState::default().depth_write(false).depth_test(gl::LESS).cull(gl::BACK));
+        assert_eq!(fake_gl::take(), vec![
+            enable(gl::DEPTH_TEST, true),
+            format!("glDepthFunc(0x{:x})", gl::LESS),
+            enable(gl::CULL_FACE, true),
+            format!("glCullFace(0x{:x})", gl::BACK),
+            enable(gl::BLEND, false),
+        ]);
+    }
+
+    #[test]
+    fn stencil_is_set_as_a_whole() {
+        let gl = fake_gl::load();
+        let mut state = StateCache::new(&gl, &fake_gl::caps());
+        state.set_pipeline(&PipelineState::default());
+        fake_gl::take();
+
+        let stencil = Stencil { func: gl::ALWAYS, reference: 1, mask: 0xff, fail: gl::KEEP, depth_fail: gl::KEEP, pass: gl::REPLACE };
+        state.set_pipeline(&PipelineState::default().stencil(stencil));
+        assert_eq!(fake_gl::take(), vec![
+            enable(gl::STENCIL_TEST, true),
+            format!("glStencilFunc(0x{:x}, 1, 0xff)", gl::ALWAYS),
+            format!("glStencilOp(0x{:x}, 0x{:x}, 0x{:x})", gl::KEEP, gl::KEEP, gl::REPLACE),
+        ]);
+    }
+
+    #[test]
+    fn invalidate_sets_everything_again() {
+        let gl = fake_gl::load();
+        let mut state = StateCache::new(&gl, &fake_gl::caps());
+        let p = PipelineState::default().depth_test(gl::LESS);
+        state.set_pipeline(&p);
+        let first = fake_gl::take();
+
+        state.invalidate();
+        state.set_pipeline(&p);
+        assert_eq!(fake_gl::take(), first);
+    }
+}
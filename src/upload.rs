@@ -0,0 +1,176 @@
+use gleam::gl;
+use gleam::gl::types::{GLuint, GLsync};
+use sdl2::sys;
+use sdl2::video::Window;
+use std::ffi::CString;
+use std::ptr;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use {GlPtr, make_texture};
+use caps::Capabilities;
+use loader::Image;
+
+// the raw SDL handles the upload thread works with: a hidden window of its
+// own, since a surface can only be current in one thread at a time (EGL
+// refuses with EGL_BAD_ACCESS), and the context, which is only ever current
+// on the upload thread. the window is made and destroyed on the main
+// thread, as SDL wants.
+struct Handles {
+    window: *mut sys::SDL_Window,
+    context: sys::SDL_GLContext,
+}
+
+unsafe impl Send for Handles {}
+
+// dropped on the upload thread however it ends, a panic included
+impl Drop for Handles {
+    fn drop(&mut self) {
+        unsafe {
+            sys::SDL_GL_MakeCurrent(self.window, ptr::null_mut());
+            sys::SDL_GL_DeleteContext(self.context);
+        }
+    }
+}
+
+// signalled once the upload thread's commands for a texture are done
+struct Fence(GLsync);
+
+unsafe impl Send for Fence {}
+
+// uploads textures from a second GL context, shared with the main one, on
+// its own thread. each finished texture comes back with a fence, which the
+// main context waits on before first use, so rendering never sees a half
+// uploaded texture and never stalls on the copy itself.
+pub struct Uploader {
+    jobs: Option<Sender<(String, Image)>>,
+    done: Receiver<(String, GLuint, Fence)>,
+    thread: Option<thread::JoinHandle<()>>,
+    window: *mut sys::SDL_Window,
+}
+
+impl Uploader {
+    // None when the driver can't give us a shared context; uploads then stay
+    // on the main thread
+    pub fn new(window: &Window, caps: &Capabilities) -> Option<Uploader> {
+        if !caps.sync_objects() {
+            return None;
+        }
+
+        let handles = unsafe {
+            // made with the GL attributes still set from the main window, so
+            // the shared context gets a matching config
+            let title = CString::new("uploader").unwrap();
+            let flags = sys::SDL_WindowFlags::SDL_WINDOW_OPENGL as u32 | sys::SDL_WindowFlags::SDL_WINDOW_HIDDEN as u32;
+            let hidden = sys::SDL_CreateWindow(title.as_ptr(), 0, 0, 1, 1, flags);
+            if hidden.is_null() {
+                warn!("no window for the upload context: {}", sdl2::get_error());
+                return None;
+            }
+
+            let main = sys::SDL_GL_GetCurrentContext();
+            sys::SDL_GL_SetAttribute(sys::SDL_GLattr::SDL_GL_SHARE_WITH_CURRENT_CONTEXT, 1);
+            let shared = sys::SDL_GL_CreateContext(hidden);
+            sys::SDL_GL_SetAttribute(sys::SDL_GLattr::SDL_GL_SHARE_WITH_CURRENT_CONTEXT, 0);
+            // creating it made it current here
+            sys::SDL_GL_MakeCurrent(window.raw(), main);
+            if shared.is_null() {
+                warn!("no shared context for uploads: {}", sdl2::get_error());
+                sys::SDL_DestroyWindow(hidden);
+                return None;
+            }
+            Handles { window: hidden, context: shared }
+        };
+        let hidden = handles.window;
+
+        let (ready_tx, ready_rx) = channel();
+        let (job_tx, job_rx) = channel::<(String, Image)>();
+        let (done_tx, done_rx) = channel();
+
+        let worker = thread::Builder::new().name("uploader".to_string()).spawn(move || {
+            let h = handles;
+            if unsafe { sys::SDL_GL_MakeCurrent(h.window, h.context) } != 0 {
+                warn!("couldn't make upload context current: {}", sdl2::get_error());
+                let _ = ready_tx.send(false);
+                return;
+            }
+            let _ = ready_tx.send(true);
+
+            let gl = unsafe { gl::GlFns::load_with(|s| {
+                let name = CString::new(s).unwrap();
+                sys::SDL_GL_GetProcAddress(name.as_ptr()) as *const _
+            })};
+
+            for (name, image) in job_rx {
+                let _span = trace_span!("texture upload", file = name.as_str()).entered();
+                let texture = make_texture(&gl, &name, &image);
+                let fence = Fence(gl.fence_sync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+                // the fence has to reach the GPU before another context can
+                // wait on it
+                gl.flush();
+                if done_tx.send((name, texture, fence)).is_err() {
+                    break;
+                }
+            }
+        });
+        let worker = match worker {
+            Ok(w)    => w,
+            Err(err) => panic!("couldn't start upload thread: {}", err),
+        };
+
+        match ready_rx.recv() {
+            Ok(true) => Some(Uploader {
+                jobs: Some(job_tx),
+                done: done_rx,
+                thread: Some(worker),
+                window: hidden,
+            }),
+            _ => {
+                let _ = worker.join();
+                unsafe { sys::SDL_DestroyWindow(hidden) };
+                None
+            },
+        }
+    }
+
+    // hands the job back if the upload thread is gone
+    pub fn upload(&self, name: &str, image: Image) -> Result<(), (String, Image)> {
+        match self.jobs {
+            Some(ref jobs) => jobs.send((name.to_string(), image)).map_err(|err| err.0),
+            None           => Ok(()),
+        }
+    }
+
+    // textures finished since the last call, safe to use from the calling
+    // (main) context. Disconnected once they've all been handed over and
+    // the upload thread is gone, which it only is if it panicked; anything
+    // it was still working on is lost.
+    pub fn poll(&self, gl: &GlPtr) -> Result<Vec<(String, GLuint)>, TryRecvError> {
+        let mut done = Vec::new();
+        loop {
+            match self.done.try_recv() {
+                Ok((name, texture, fence)) => {
+                    // a server-side wait; doesn't block this thread
+                    gl.wait_sync(fence.0, 0, gl::TIMEOUT_IGNORED);
+                    gl.delete_sync(fence.0);
+                    done.push((name, texture));
+                },
+                Err(TryRecvError::Empty) => return Ok(done),
+                Err(TryRecvError::Disconnected) if done.is_empty() => return Err(TryRecvError::Disconnected),
+                // the next call reports it
+                Err(TryRecvError::Disconnected) => return Ok(done),
+            }
+        }
+    }
+}
+
+impl Drop for Uploader {
+    fn drop(&mut self) {
+        self.jobs = None;
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+        // the thread has released its context
+        unsafe { sys::SDL_DestroyWindow(self.window) };
+    }
+}
@@ -0,0 +1,461 @@
+use gleam::gl;
+use gleam::gl::types::{GLuint, GLint, GLenum, GLfloat, GLsizei};
+
+use caps::Capabilities;
+use buffer::Buffer;
+use state::StateCache;
+
+// GLES2's OES_vertex_half_float uses its own enum for the same type
+pub const HALF_FLOAT_OES: GLenum = 0x8D61;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Packing {
+    // all attributes of a vertex next to each other in a single buffer
+    Interleaved,
+    // one tightly packed buffer per attribute
+    Separate,
+}
+
+// how an attribute is stored in the buffer. source data is always float and
+// gets converted when packed; the normalized integer formats map [-1,1]
+// (signed) or [0,1] (unsigned) onto the full integer range.
+// not every format has a user outside the tests yet
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Float,
+    HalfFloat,
+    NormalizedByte,
+    NormalizedUnsignedByte,
+    NormalizedShort,
+    NormalizedUnsignedShort,
+    // signed normalized x, y, z in 10 bits each and w in 2, all in one
+    // 32-bit word. meant for normals and tangents; w defaults to 0 when
+    // the source has only three components.
+    Packed2101010,
+}
+
+impl Format {
+    fn component_size(self) -> usize {
+        match self {
+            Format::Float                   => 4,
+            Format::HalfFloat               => 2,
+            Format::NormalizedByte          => 1,
+            Format::NormalizedUnsignedByte  => 1,
+            Format::NormalizedShort         => 2,
+            Format::NormalizedUnsignedShort => 2,
+            Format::Packed2101010           => 4,
+        }
+    }
+
+    fn gl_type(self, half_float: Option<GLenum>) -> GLenum {
+        match self {
+            Format::Float                   => gl::FLOAT,
+            Format::HalfFloat               => half_float.expect("half-float attribute without half-float support"),
+            Format::NormalizedByte          => gl::BYTE,
+            Format::NormalizedUnsignedByte  => gl::UNSIGNED_BYTE,
+            Format::NormalizedShort         => gl::SHORT,
+            Format::NormalizedUnsignedShort => gl::UNSIGNED_SHORT,
+            Format::Packed2101010           => gl::INT_2_10_10_10_REV,
+        }
+    }
+
+    fn normalized(self) -> bool {
+        !matches!(self, Format::Float | Format::HalfFloat)
+    }
+
+    fn write(self, v: GLfloat, out: &mut Vec<u8>) {
+        match self {
+            Format::Float                   => out.extend_from_slice(&v.to_bits().to_le_bytes()),
+            Format::HalfFloat               => out.extend_from_slice(&to_half(v).to_le_bytes()),
+            Format::NormalizedByte          => out.push((v.clamp(-1.0, 1.0) * 127.0).round() as i8 as u8),
+            Format::NormalizedUnsignedByte  => out.push((v.clamp(0.0, 1.0) * 255.0).round() as u8),
+            Format::NormalizedShort         => out.extend_from_slice(&((v.clamp(-1.0, 1.0) * 32767.0).round() as i16).to_le_bytes()),
+            Format::NormalizedUnsignedShort => out.extend_from_slice(&((v.clamp(0.0, 1.0) * 65535.0).round() as u16).to_le_bytes()),
+            Format::Packed2101010           => panic!("packed formats are written a vertex at a time"),
+        }
+    }
+
+    fn write_packed(v: &[GLfloat], out: &mut Vec<u8>) {
+        let c = |i: usize, bits: u32| {
+            let max = ((1 << (bits - 1)) - 1) as GLfloat;
+            let x = v.get(i).cloned().unwrap_or(0.0);
+            ((x.clamp(-1.0, 1.0) * max).round() as i32 as u32) & ((1 << bits) - 1)
+        };
+        let word = c(0, 10) | c(1, 10) << 10 | c(2, 10) << 20 | c(3, 2) << 30;
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+// x >> shift, rounded to nearest with ties to even
+fn round_shift(x: u32, shift: u32) -> u32 {
+    let rest = x & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    let r = x >> shift;
+    if rest > half || (rest == half && r & 1 != 0) { r + 1 } else { r }
+}
+
+// IEEE 754 binary16, round to nearest with ties to even, flushing values too
+// small for a half's subnormals to zero
+pub fn to_half(v: GLfloat) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x007f_ffff;
+
+    if exp == 0xff {
+        // inf stays inf, nan stays (quiet) nan
+        return sign | 0x7c00 | if mant != 0 { 0x0200 } else { 0 };
+    }
+
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        sign | 0x7c00
+    } else if e <= 0 {
+        if e < -10 {
+            return sign;
+        }
+        // rounding up to 0x400 gives the smallest normal, as it should
+        sign | round_shift(mant | 0x0080_0000, (14 - e) as u32) as u16
+    } else {
+        // a carry out of the mantissa correctly bumps the exponent, up to
+        // inf past the largest half
+        sign | (((e as u32) << 10) + round_shift(mant, 13)) as u16
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Attribute {
+    // None when the program doesn't use the attribute (the compiler may
+    // have optimized it out): its components are still in the source
+    // vertices, but nothing is stored or bound for it
+    pub location: Option<GLuint>,
+    pub components: usize,
+    pub format: Format,
+}
+
+impl Attribute {
+    // attributes start on 4-byte boundaries, which some drivers require
+    fn size(&self) -> usize {
+        if self.location.is_none() {
+            return 0;
+        }
+        match self.format {
+            Format::Packed2101010 => 4,
+            _ => (self.components * self.format.component_size() + 3) & !3,
+        }
+    }
+
+    // the packed format always sources all four components
+    fn gl_components(&self) -> GLint {
+        match self.format {
+            Format::Packed2101010 => 4,
+            _ => self.components as GLint,
+        }
+    }
+}
+
+// describes how the attributes of a vertex are laid out across one or more
+// buffers. source data is always given as interleaved floats, in attribute
+// order.
+#[derive(Clone, Debug)]
+pub struct VertexLayout {
+    pub packing: Packing,
+    pub attributes: Vec<Attribute>,
+    // type passed for Format::HalfFloat, from
+    // Capabilities::half_float_vertices. None if the context can't source
+    // halves, in which case half-float attributes are stored as floats.
+    pub half_float: Option<GLenum>,
+    // whether Format::Packed2101010 can be sourced; stored as floats if not
+    pub packed: bool,
+}
+
+impl VertexLayout {
+    pub fn new(packing: Packing, caps: &Capabilities) -> VertexLayout {
+        VertexLayout {
+            packing,
+            attributes: Vec::new(),
+            half_float: caps.half_float_vertices(),
+            packed: caps.packed_vertex_formats(),
+        }
+    }
+
+    pub fn attribute(self, location: GLint, components: usize) -> VertexLayout {
+        self.attribute_as(location, components, Format::Float)
+    }
+
+    // location is as returned by get_attrib_location; a negative one marks
+    // the attribute inactive
+    pub fn attribute_as(mut self, location: GLint, components: usize, format: Format) -> VertexLayout {
+        if location < 0 {
+            debug!("skipping inactive vertex attribute {}", self.attributes.len());
+        }
+        let format = match format {
+            Format::HalfFloat     if self.half_float.is_none() => Format::Float,
+            Format::Packed2101010 if !self.packed              => Format::Float,
+            _ => format,
+        };
+        self.attributes.push(Attribute {
+            location: if location < 0 { None } else { Some(location as GLuint) },
+            components,
+            format,
+        });
+        self
+    }
+
+    // floats per source vertex, across all attributes
+    pub fn vertex_size(&self) -> usize {
+        self.attributes.iter().map(|a| a.components).sum()
+    }
+
+    // bytes per vertex once packed, across all attributes
+    pub fn stride(&self) -> usize {
+        self.attributes.iter().map(|a| a.size()).sum()
+    }
+
+    // separate packing keeps a (possibly empty) buffer for inactive
+    // attributes too, so buffers still line up with attributes
+    pub fn buffer_count(&self) -> usize {
+        match self.packing {
+            Packing::Interleaved => 1,
+            Packing::Separate    => self.attributes.len(),
+        }
+    }
+
+    // converts interleaved float source vertices into the data for each
+    // buffer, in each attribute's storage format
+    pub fn pack(&self, vertices: &[GLfloat]) -> Vec<Vec<u8>> {
+        let size = self.vertex_size();
+        let count = vertices.len() / size;
+        let mut buffers = vec![Vec::with_capacity(count * self.stride()); self.buffer_count()];
+
+        for v in vertices.chunks(size) {
+            let mut offset = 0;
+            for (i, a) in self.attributes.iter().enumerate() {
+                if a.location.is_none() {
+                    offset += a.components;
+                    continue;
+                }
+                let out = match self.packing {
+                    Packing::Interleaved => &mut buffers[0],
+                    Packing::Separate    => &mut buffers[i],
+                };
+                let start = out.len();
+                let src = &v[offset..offset+a.components];
+                if a.format == Format::Packed2101010 {
+                    Format::write_packed(src, out);
+                } else {
+                    for &c in src {
+                        a.format.write(c, out);
+                    }
+                }
+                out.resize(start + a.size(), 0);
+                offset += a.components;
+            }
+        }
+
+        buffers
+    }
+
+    pub fn bind(&self, state: &mut StateCache, buffers: &[Buffer]) {
+        if buffers.len() != self.buffer_count() {
+            panic!("vertex layout needs {} buffers, got {}", self.buffer_count(), buffers.len());
+        }
+        self.bind_with(state, |i| buffers[i].buffer, 0);
+    }
+
+    // binds an interleaved layout whose vertices start base bytes into
+    // buffer, as handed out by StreamBuffer::push
+    pub fn bind_at(&self, state: &mut StateCache, buffer: GLuint, base: usize) {
+        if self.packing != Packing::Interleaved {
+            panic!("only interleaved vertex layouts can be bound at an offset");
+        }
+        self.bind_with(state, |_| buffer, base);
+    }
+
+    fn bind_with<F: Fn(usize) -> GLuint>(&self, state: &mut StateCache, buffer: F, base: usize) {
+        let mut offset = 0;
+        for (i, a) in self.attributes.iter().enumerate() {
+            let location = match a.location {
+                Some(l) => l,
+                None    => continue,
+            };
+            let (buffer, stride, start) = match self.packing {
+                Packing::Interleaved => (buffer(0), self.stride(), base + offset),
+                Packing::Separate    => (buffer(i), a.size(), base),
+            };
+            state.bind_buffer(gl::ARRAY_BUFFER, buffer);
+            state.vertex_attrib_pointer(
+                location,
+                a.gl_components(),
+                a.format.gl_type(self.half_float),
+                a.format.normalized(),
+                stride as GLsizei,
+                start as GLuint);
+            offset += a.size();
+        }
+
+        let mask = self.attributes.iter().filter_map(|a| a.location).fold(0, |m, l| m | 1 << l);
+        state.set_attribs(mask);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn caps(version: (u32, u32)) -> Capabilities {
+        Capabilities {
+            es: false,
+            version,
+            glsl_version: (1, 1),
+            extensions: HashSet::new(),
+            max_texture_size: 2048,
+            max_texture_units: 8,
+            max_vertex_attribs: 16,
+        }
+    }
+
+    #[test]
+    fn half_zero() {
+        assert_eq!(to_half(0.0), 0x0000);
+        assert_eq!(to_half(-0.0), 0x8000);
+        assert_eq!(to_half(1.0), 0x3c00);
+        assert_eq!(to_half(-2.0), 0xc000);
+    }
+
+    #[test]
+    fn half_subnormals() {
+        let tiny = 2.0f32.powi(-24);
+        assert_eq!(to_half(tiny), 0x0001);
+        assert_eq!(to_half(1023.0 * tiny), 0x03ff);
+        // the smallest normal
+        assert_eq!(to_half(2.0f32.powi(-14)), 0x0400);
+        // half of the smallest subnormal ties to zero, anything above
+        // rounds up to it
+        assert_eq!(to_half(0.5 * tiny), 0x0000);
+        assert_eq!(to_half(0.75 * tiny), 0x0001);
+        assert_eq!(to_half(1.5 * tiny), 0x0002);
+        assert_eq!(to_half(2.5 * tiny), 0x0002);
+        assert_eq!(to_half(0.25 * tiny), 0x0000);
+        assert_eq!(to_half(-tiny), 0x8001);
+    }
+
+    #[test]
+    fn half_overflow() {
+        assert_eq!(to_half(65504.0), 0x7bff);
+        // halfway to the next power of two rounds to even, i.e. inf
+        assert_eq!(to_half(65520.0), 0x7c00);
+        assert_eq!(to_half(65519.0), 0x7bff);
+        assert_eq!(to_half(1.0e6), 0x7c00);
+        assert_eq!(to_half(-1.0e6), 0xfc00);
+        assert_eq!(to_half(f32::INFINITY), 0x7c00);
+        assert_eq!(to_half(f32::NEG_INFINITY), 0xfc00);
+    }
+
+    #[test]
+    fn half_nan() {
+        let h = to_half(f32::NAN);
+        assert_eq!(h & 0x7c00, 0x7c00);
+        assert_ne!(h & 0x03ff, 0);
+    }
+
+    #[test]
+    fn half_rounding() {
+        let ulp = 2.0f32.powi(-10);
+        // ties go to the even mantissa
+        assert_eq!(to_half(1.0 + 0.5 * ulp), 0x3c00);
+        assert_eq!(to_half(1.0 + 1.5 * ulp), 0x3c02);
+        // anything past a tie rounds to nearest
+        assert_eq!(to_half(1.0 + 0.5 * ulp + 2.0f32.powi(-20)), 0x3c01);
+        assert_eq!(to_half(1.0 + 0.49 * ulp), 0x3c00);
+        // a carry out of the mantissa bumps the exponent
+        assert_eq!(to_half(2.0 - 0.25 * ulp), 0x4000);
+    }
+
+    #[test]
+    fn normalized_formats() {
+        let layout = VertexLayout::new(Packing::Interleaved, &caps((2, 1)))
+            .attribute_as(0, 2, Format::NormalizedByte)
+            .attribute_as(1, 1, Format::NormalizedUnsignedByte)
+            .attribute_as(2, 2, Format::NormalizedShort)
+            .attribute_as(3, 1, Format::NormalizedUnsignedShort);
+        assert_eq!(layout.stride(), 4 + 4 + 4 + 4);
+
+        let packed = layout.pack(&[1.0, -2.0, 0.5, -1.0, 0.0, 1.0]);
+        let mut expected = vec![127, (-127i8) as u8, 0, 0, 128, 0, 0, 0];
+        expected.extend_from_slice(&(-32767i16).to_le_bytes());
+        expected.extend_from_slice(&0i16.to_le_bytes());
+        expected.extend_from_slice(&65535u16.to_le_bytes());
+        expected.extend_from_slice(&[0, 0]);
+        assert_eq!(packed, vec![expected]);
+    }
+
+    #[test]
+    fn half_float_needs_support() {
+        let layout = VertexLayout::new(Packing::Interleaved, &caps((3, 0)))
+            .attribute_as(0, 2, Format::HalfFloat);
+        assert_eq!(layout.attributes[0].format, Format::HalfFloat);
+        assert_eq!(layout.pack(&[1.0, -2.0]), vec![vec![0x00, 0x3c, 0x00, 0xc0]]);
+
+        // GL 2.1 without ARB_half_float_vertex falls back to floats
+        let layout = VertexLayout::new(Packing::Interleaved, &caps((2, 1)))
+            .attribute_as(0, 2, Format::HalfFloat);
+        assert_eq!(layout.attributes[0].format, Format::Float);
+        assert_eq!(layout.stride(), 8);
+    }
+
+    // GL 4.2's rule for signed normalized values
+    fn unpack_2101010(word: u32) -> [GLfloat; 4] {
+        let c = |shift: u32, bits: u32| {
+            let v = ((word << (32 - shift - bits)) as i32) >> (32 - bits);
+            (v as GLfloat / ((1 << (bits - 1)) - 1) as GLfloat).max(-1.0)
+        };
+        [c(0, 10), c(10, 10), c(20, 10), c(30, 2)]
+    }
+
+    #[test]
+    fn packed_round_trip() {
+        let layout = VertexLayout::new(Packing::Interleaved, &caps((3, 3)))
+            .attribute_as(0, 4, Format::Packed2101010)
+            .attribute_as(1, 3, Format::Packed2101010);
+        assert_eq!(layout.stride(), 8);
+
+        let normal = [0.0, 0.6, -0.8];
+        let mut src = vec![1.0, -1.0, 0.25, -1.0];
+        src.extend_from_slice(&normal);
+        let packed = layout.pack(&src);
+        let word = |i: usize| u32::from_le_bytes([packed[0][i], packed[0][i+1], packed[0][i+2], packed[0][i+3]]);
+
+        let a = unpack_2101010(word(0));
+        for (&x, &y) in a.iter().zip(src[..4].iter()) {
+            assert!((x - y).abs() <= 0.5 / 511.0, "{:?} != {:?}", a, &src[..4]);
+        }
+        // a three component source gets w = 0
+        let b = unpack_2101010(word(4));
+        for (&x, &y) in b.iter().zip(normal.iter().chain(Some(&0.0))) {
+            assert!((x - y).abs() <= 0.5 / 511.0, "{:?} != {:?}", b, normal);
+        }
+    }
+
+    #[test]
+    fn packed_needs_support() {
+        let layout = VertexLayout::new(Packing::Interleaved, &caps((2, 1)))
+            .attribute_as(0, 3, Format::Packed2101010);
+        assert_eq!(layout.attributes[0].format, Format::Float);
+        assert_eq!(layout.stride(), 12);
+    }
+
+    #[test]
+    fn inactive_attributes() {
+        let layout = VertexLayout::new(Packing::Separate, &caps((3, 0)))
+            .attribute(0, 1)
+            .attribute(-1, 2)
+            .attribute(1, 1);
+        assert_eq!(layout.vertex_size(), 4);
+        assert_eq!(layout.stride(), 8);
+        let packed = layout.pack(&[1.0, 9.0, 9.0, 2.0]);
+        assert_eq!(packed, vec![1.0f32.to_le_bytes().to_vec(), vec![], 2.0f32.to_le_bytes().to_vec()]);
+    }
+}
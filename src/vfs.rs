@@ -0,0 +1,342 @@
+use notify;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use assets::Watcher;
+
+// where assets come from. loaders only ever see this, so the same code can
+// read from the filesystem, from copies built into the binary, or from
+// places that have no filesystem at all.
+pub trait Vfs: Send + Sync {
+    fn read(&self, path: &str) -> io::Result<Cow<'static, [u8]>>;
+
+    fn exists(&self, path: &str) -> bool;
+
+    // the asset as a file on disk, for the few things (shared libraries,
+    // change notification) that need one
+    fn real_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+
+    fn open(&self, path: &str) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(Cursor::new(self.read(path)?)))
+    }
+
+    // an asset with no file behind it never changes, so there's nothing
+    // to watch
+    fn watch(&self, watcher: &mut Watcher, path: &str) -> notify::Result<()> {
+        match self.real_path(path) {
+            Some(p) => watcher.watch(p),
+            None    => Ok(()),
+        }
+    }
+}
+
+fn not_found(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, message)
+}
+
+// overrides the rest of the search order
+static ENV_VAR: &str = "HELLO_GL_ASSETS";
+
+// the OS filesystem, searched in order: the directory given on the command
+// line, $HELLO_GL_ASSETS, the current directory, the executable's directory
+// and the source tree it was built from
+pub struct Dirs {
+    roots: Vec<PathBuf>,
+}
+
+impl Dirs {
+    pub fn new(dir: Option<&str>) -> Dirs {
+        let mut roots = Vec::new();
+        if let Some(d) = dir {
+            roots.push(PathBuf::from(d));
+        }
+        if let Some(d) = env::var_os(ENV_VAR) {
+            roots.push(PathBuf::from(d));
+        }
+        if let Ok(d) = env::current_dir() {
+            roots.push(d);
+        }
+        if let Some(d) = env::current_exe().ok().and_then(|e| e.parent().map(Path::to_path_buf)) {
+            roots.push(d);
+        }
+        roots.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+        // the same directory may turn up more than once
+        let mut seen = HashSet::new();
+        roots.retain(|r| seen.insert(r.canonicalize().unwrap_or_else(|_| r.clone())));
+        debug!("looking for assets in {:?}", roots);
+        Dirs { roots }
+    }
+}
+
+impl Vfs for Dirs {
+    fn read(&self, path: &str) -> io::Result<Cow<'static, [u8]>> {
+        match self.real_path(path) {
+            Some(p) => fs::read(p).map(Cow::Owned),
+            None    => {
+                let tried: Vec<String> = self.roots.iter().map(|r| r.join(path).display().to_string()).collect();
+                Err(not_found(format!("couldn't find {} (tried {})", path, tried.join(", "))))
+            },
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.real_path(path).is_some()
+    }
+
+    fn real_path(&self, path: &str) -> Option<PathBuf> {
+        self.roots.iter().map(|r| r.join(path)).find(|p| p.exists())
+    }
+}
+
+// the default shaders, built in so the binary runs on its own
+static EMBEDDED: [(&str, &[u8]); 6] = [
+    ("hello-gl.v.glsl", include_bytes!("../hello-gl.v.glsl")),
+    ("hello-gl.f.glsl", include_bytes!("../hello-gl.f.glsl")),
+    ("debug.v.glsl",    include_bytes!("../debug.v.glsl")),
+    ("debug.f.glsl",    include_bytes!("../debug.f.glsl")),
+    ("background.v.glsl", include_bytes!("../background.v.glsl")),
+    ("background.f.glsl", include_bytes!("../background.f.glsl")),
+];
+
+pub struct Embedded;
+
+impl Vfs for Embedded {
+    fn read(&self, path: &str) -> io::Result<Cow<'static, [u8]>> {
+        match EMBEDDED.iter().find(|e| e.0 == path) {
+            Some(e) => Ok(Cow::Borrowed(e.1)),
+            None    => Err(not_found(format!("{} isn't built in", path))),
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        EMBEDDED.iter().any(|e| e.0 == path)
+    }
+}
+
+// a pak archive: the bytes "HGLPAK1\0", a little-endian u32 count of
+// entries, then for each entry a u32 name length, the name (UTF-8, with /
+// separators), a u64 offset into the file and a u64 size, then the data.
+// the whole archive is read into memory when it's mounted.
+pub struct Pak {
+    data: Vec<u8>,
+    entries: HashMap<String, Range<usize>>,
+}
+
+pub const PAK_MAGIC: &[u8; 8] = b"HGLPAK1\0";
+
+impl Pak {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Pak> {
+        let data = fs::read(path.as_ref())?;
+        let entries = match Pak::index(&data) {
+            Ok(e)    => e,
+            Err(err) => return Err(invalid(format!("{}: {}", path.as_ref().display(), err))),
+        };
+        info!("mounted {}, {} files", path.as_ref().display(), entries.len());
+        Ok(Pak { data, entries })
+    }
+
+    fn index(data: &[u8]) -> io::Result<HashMap<String, Range<usize>>> {
+        let mut r = Cursor::new(data);
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != PAK_MAGIC {
+            return Err(invalid("not a pak".to_string()));
+        }
+        let mut entries = HashMap::new();
+        for _ in 0..read_u32(&mut r)? {
+            let len = read_u32(&mut r)? as usize;
+            if len > data.len() {
+                return Err(invalid("bad name length".to_string()));
+            }
+            let mut name = vec![0; len];
+            r.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| invalid("name isn't UTF-8".to_string()))?;
+            let offset = read_u64(&mut r)? as usize;
+            let size = read_u64(&mut r)? as usize;
+            match offset.checked_add(size) {
+                Some(end) if end <= data.len() => entries.insert(name, offset..end),
+                _ => return Err(invalid(format!("{} runs past the end", name))),
+            };
+        }
+        Ok(entries)
+    }
+
+    // writes the named files out as a pak
+    pub fn write<P: AsRef<Path>>(path: P, files: &[(&str, Vec<u8>)]) -> io::Result<()> {
+        let index_size: usize = files.iter().map(|f| 4 + f.0.len() + 16).sum();
+        let mut offset = PAK_MAGIC.len() + 4 + index_size;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(PAK_MAGIC);
+        out.extend_from_slice(&(files.len() as u32).to_le_bytes());
+        for (name, data) in files {
+            out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            offset += data.len();
+        }
+        for (_, data) in files {
+            out.extend_from_slice(data);
+        }
+        fs::write(path, out)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+impl Vfs for Pak {
+    fn read(&self, path: &str) -> io::Result<Cow<'static, [u8]>> {
+        match self.entries.get(path) {
+            Some(r) => Ok(Cow::Owned(self.data[r.clone()].to_vec())),
+            None    => Err(not_found(format!("{} isn't in the pak", path))),
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+}
+
+// several sources stacked; the first that has an asset provides it
+pub struct Layered {
+    layers: Vec<Box<dyn Vfs>>,
+}
+
+impl Layered {
+    pub fn new() -> Layered {
+        Layered { layers: Vec::new() }
+    }
+
+    pub fn with<V: Vfs + 'static>(mut self, layer: V) -> Layered {
+        self.layers.push(Box::new(layer));
+        self
+    }
+}
+
+impl Vfs for Layered {
+    fn read(&self, path: &str) -> io::Result<Cow<'static, [u8]>> {
+        let mut error = None;
+        for layer in &self.layers {
+            match layer.read(path) {
+                Ok(data) => return Ok(data),
+                // the first layer's complaint is the most useful one
+                Err(err) => if error.is_none() { error = Some(err) },
+            }
+        }
+        Err(error.unwrap_or_else(|| not_found(format!("couldn't find {}", path))))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.layers.iter().any(|l| l.exists(path))
+    }
+
+    fn real_path(&self, path: &str) -> Option<PathBuf> {
+        // only if that's where reads would come from
+        self.layers.iter().find(|l| l.exists(path)).and_then(|l| l.real_path(path))
+    }
+}
+
+// the filesystem over the mounted archive, if any, over the built-in
+// copies, so files on disk can still be edited and reloaded in place
+pub fn default(dir: Option<&str>, pak: Option<Pak>) -> Layered {
+    let mut vfs = Layered::new().with(Dirs::new(dir));
+    if let Some(p) = pak {
+        vfs = vfs.with(p);
+    }
+    vfs.with(Embedded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a pak's header and index, entries given as (name, offset, size)
+    fn index_bytes(entries: &[(&str, u64, u64)]) -> Vec<u8> {
+        let mut out = PAK_MAGIC.to_vec();
+        out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for &(name, offset, size) in entries {
+            out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&size.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn pak_round_trip() {
+        let path = env::temp_dir().join(format!("hello-gl-test-{}.pak", std::process::id()));
+        let files = [("a.glsl", b"void main() {}".to_vec()), ("dir/empty", Vec::new()), ("b.bin", vec![0, 1, 2, 255])];
+        Pak::write(&path, &files).unwrap();
+        let pak = Pak::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        let pak = pak.unwrap();
+        for (name, data) in files.iter() {
+            assert!(pak.exists(name));
+            assert_eq!(&*pak.read(name).unwrap(), &data[..]);
+        }
+        assert!(!pak.exists("c"));
+        assert_eq!(pak.read("c").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn pak_bad_magic() {
+        let mut data = index_bytes(&[]);
+        data[0] = b'X';
+        assert_eq!(Pak::index(&data).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(Pak::index(b"HGL").is_err());
+    }
+
+    #[test]
+    fn pak_truncated_index() {
+        let data = index_bytes(&[("a", 0, 0), ("b", 0, 0)]);
+        // cut off partway through the second entry
+        let err = Pak::index(&data[..data.len() - 10]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // a name length longer than the whole file
+        let mut data = index_bytes(&[]);
+        data[8] = 1;
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(Pak::index(&data).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn pak_entry_past_the_end() {
+        // the data starts right after the index
+        let len = index_bytes(&[("a", 0, 0)]).len() as u64;
+        let data = [index_bytes(&[("a", len, 4)]), b"abc".to_vec()].concat();
+        assert_eq!(Pak::index(&data).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // one byte less fits
+        let data = [index_bytes(&[("a", len, 3)]), b"abc".to_vec()].concat();
+        assert_eq!(Pak::index(&data).unwrap()["a"], len as usize..len as usize + 3);
+
+        // and an offset and size whose sum overflows doesn't wrap round
+        let data = index_bytes(&[("a", u64::MAX, 2)]);
+        assert_eq!(Pak::index(&data).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}